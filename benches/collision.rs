@@ -0,0 +1,84 @@
+//! Compares collision queries against every segment with queries through the segment grid,
+//! and sweeping moves with pushing out of walls after them.
+//! Run with `cargo bench --bench collision`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use nalgebra::Vector2;
+
+use ray_tracer::collision::{self, Segment};
+use ray_tracer::map::Map;
+
+/// A square map of `size` cells with walls around the edge and about a third of the inside
+/// filled at random, always the same for a given size
+fn scattered(size: u32) -> Map {
+    let mut seed: u32 = 0x2545f491;
+    let mut random = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        seed
+    };
+
+    let mut map = Map::new(size, size, 1.0);
+    for y in 0..size {
+        for x in 0..size {
+            let edge = x == 0 || y == 0 || x == size - 1 || y == size - 1;
+            map.set(x, y, (edge || random() % 3 == 0) as u16);
+        }
+    }
+    map.regenerate_segments();
+    map
+}
+
+/// Moves starting in the middle of empty cells, each going a different way
+fn moves(map: &Map, count: usize) -> Vec<(Vector2<f32>, Vector2<f32>)> {
+    let empty: Vec<(u32, u32)> = (0..map.height)
+        .flat_map(|y| (0..map.width).map(move |x| (x, y)))
+        .filter(|&(x, y)| map.get(x, y) == 0)
+        .collect();
+
+    (0..count).map(|i| {
+        let (x, y) = empty[i * 7919 % empty.len()];
+        let (ox, oy) = map.cell_origin(x, y);
+        let angle = i as f32 * 2.399;
+        (Vector2::new(ox + 0.5, oy + 0.5), Vector2::new(angle.cos(), angle.sin()) * 0.2)
+    }).collect()
+}
+
+fn slide_move(c: &mut Criterion) {
+    let mut group = c.benchmark_group("slide_move");
+
+    for size in [16, 64, 256] {
+        let map = scattered(size);
+        let moves = moves(&map, 100);
+        let segments: &[Segment] = &map.segments;
+
+        group.bench_with_input(BenchmarkId::new("brute_force", size), &moves, |b, moves| {
+            b.iter(|| moves.iter().map(|&(pos, vel)| collision::slide_move(pos, 0.25, vel, segments)).sum::<Vector2<f32>>())
+        });
+        group.bench_with_input(BenchmarkId::new("grid", size), &moves, |b, moves| {
+            b.iter(|| moves.iter().map(|&(pos, vel)| collision::slide_move(pos, 0.25, vel, &map.segment_grid)).sum::<Vector2<f32>>())
+        });
+    }
+
+    group.finish();
+}
+
+/// Sweeping costs more than pushing out afterwards, this shows how much
+fn sweep_move(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sweep_move");
+
+    for size in [16, 64, 256] {
+        let map = scattered(size);
+        let moves = moves(&map, 100);
+
+        group.bench_with_input(BenchmarkId::new("grid", size), &moves, |b, moves| {
+            b.iter(|| moves.iter().map(|&(pos, vel)| collision::sweep_move(pos, 0.25, vel, &map.segment_grid)).sum::<Vector2<f32>>())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, slide_move, sweep_move);
+criterion_main!(benches);
@@ -0,0 +1,246 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context};
+use sdl2::controller::{Axis, Button};
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use serde::{Deserialize, Serialize};
+
+/// Something the player can do, independent of which inputs trigger it
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    StrafeLeft,
+    StrafeRight,
+    TurnLeft,
+    TurnRight,
+    Sprint,
+    Use,
+    Fire
+}
+
+impl Action {
+    pub const ALL: [Action; 9] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::StrafeLeft,
+        Action::StrafeRight,
+        Action::TurnLeft,
+        Action::TurnRight,
+        Action::Sprint,
+        Action::Use,
+        Action::Fire
+    ];
+
+    /// This action's bit in an action set, see `TickInput`
+    pub fn bit(self) -> u16 {
+        1 << self as u16
+    }
+}
+
+/// A physical input that can trigger an action. Written in config files as `key:<name>`,
+/// `mouse:<button>`, `controller:<button>` or `axis:<axis>`, using SDL's key and controller button
+/// and axis names. Axes count as pressed while pushed past `input::TRIGGER_THRESHOLD`, which is mostly
+/// useful for triggers.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Binding {
+    Key(Keycode),
+    Mouse(MouseButton),
+    Controller(Button),
+    Axis(Axis)
+}
+
+fn mouse_button_name(button: MouseButton) -> &'static str {
+    match button {
+        MouseButton::Left => "left",
+        MouseButton::Middle => "middle",
+        MouseButton::Right => "right",
+        MouseButton::X1 => "x1",
+        MouseButton::X2 => "x2",
+        MouseButton::Unknown => "unknown"
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "key:{}", key.name()),
+            Binding::Mouse(button) => write!(f, "mouse:{}", mouse_button_name(*button)),
+            Binding::Controller(button) => write!(f, "controller:{}", button.string()),
+            Binding::Axis(axis) => write!(f, "axis:{}", axis.string())
+        }
+    }
+}
+
+impl FromStr for Binding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (kind, name) = s.split_once(':')
+            .ok_or_else(|| anyhow!("binding {:?} should look like key:<name>, mouse:<button>, controller:<button> or axis:<axis>", s))?;
+
+        match kind {
+            "key" => Keycode::from_name(name)
+                .map(Binding::Key)
+                .ok_or_else(|| anyhow!("unknown key {:?}", name)),
+            "mouse" => [MouseButton::Left, MouseButton::Middle, MouseButton::Right, MouseButton::X1, MouseButton::X2, MouseButton::Unknown]
+                .into_iter()
+                .find(|&button| mouse_button_name(button) == name)
+                .map(Binding::Mouse)
+                .ok_or_else(|| anyhow!("unknown mouse button {:?}", name)),
+            "controller" => Button::from_string(name)
+                .map(Binding::Controller)
+                .ok_or_else(|| anyhow!("unknown controller button {:?}", name)),
+            "axis" => Axis::from_string(name)
+                .map(Binding::Axis)
+                .ok_or_else(|| anyhow!("unknown controller axis {:?}", name)),
+            _ => Err(anyhow!("unknown binding kind {:?} in {:?}", kind, s))
+        }
+    }
+}
+
+impl TryFrom<String> for Binding {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> anyhow::Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<Binding> for String {
+    fn from(binding: Binding) -> Self {
+        binding.to_string()
+    }
+}
+
+/// Which inputs trigger each action
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Bindings {
+    pub actions: BTreeMap<Action, Vec<Binding>>
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use Binding::*;
+
+        let actions = [
+            (Action::MoveForward, vec![Key(Keycode::W), Controller(Button::DPadUp)]),
+            (Action::MoveBackward, vec![Key(Keycode::S), Controller(Button::DPadDown)]),
+            (Action::StrafeLeft, vec![Key(Keycode::A), Controller(Button::DPadLeft)]),
+            (Action::StrafeRight, vec![Key(Keycode::D), Controller(Button::DPadRight)]),
+            (Action::TurnLeft, vec![Key(Keycode::Left)]),
+            (Action::TurnRight, vec![Key(Keycode::Right)]),
+            (Action::Sprint, vec![Key(Keycode::LShift), Controller(Button::LeftStick), Axis(sdl2::controller::Axis::TriggerLeft)]),
+            (Action::Use, vec![Key(Keycode::E), Mouse(MouseButton::Right), Controller(Button::A)]),
+            (Action::Fire, vec![Key(Keycode::Space), Mouse(MouseButton::Left), Controller(Button::RightShoulder), Axis(sdl2::controller::Axis::TriggerRight)])
+        ];
+
+        Self {
+            actions: actions.into_iter().collect()
+        }
+    }
+}
+
+impl Bindings {
+    /// Returns the inputs bound to `action`
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.actions.get(&action).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Replaces the inputs bound to `action`
+    pub fn bind(&mut self, action: Action, bindings: Vec<Binding>) {
+        self.actions.insert(action, bindings);
+    }
+
+    /// Parses bindings, keeping the defaults for any action the string doesn't mention, so that
+    /// actions added since a bindings file was written still do something
+    pub fn parse(string: &str) -> anyhow::Result<Self> {
+        let parsed: Self = toml::from_str(string).map_err(|e| anyhow!("{}", e))?;
+
+        let mut bindings = Self::default();
+        bindings.actions.extend(parsed.actions);
+        Ok(bindings)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let string = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read bindings {}", path.display()))?;
+
+        Self::parse(&string)
+            .with_context(|| format!("failed to parse bindings {}", path.display()))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let string = toml::to_string(self)?;
+
+        std::fs::write(path, string)
+            .with_context(|| format!("failed to write bindings {}", path.display()))
+    }
+
+    /// Loads bindings from `path`, or writes the defaults there if it doesn't exist yet
+    pub fn load_or_create<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if path.exists() {
+            return Self::load(path);
+        }
+
+        let bindings = Self::default();
+        bindings.save(path)?;
+        Ok(bindings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_kind_of_binding_round_trips() {
+        let mut bindings = vec![
+            Binding::Key(Keycode::W),
+            Binding::Key(Keycode::LShift),
+            Binding::Controller(Button::A),
+            Binding::Controller(Button::DPadLeft),
+            Binding::Axis(Axis::TriggerRight)
+        ];
+        bindings.extend([MouseButton::Left, MouseButton::Middle, MouseButton::Right, MouseButton::X1, MouseButton::X2, MouseButton::Unknown]
+            .map(Binding::Mouse));
+
+        for binding in bindings {
+            assert_eq!(binding.to_string().parse::<Binding>().unwrap(), binding, "{}", binding);
+        }
+    }
+
+    #[test]
+    fn saved_bindings_load_back() {
+        let mut bindings = Bindings::default();
+        bindings.bind(Action::Fire, vec![Binding::Mouse(MouseButton::Unknown), Binding::Axis(Axis::LeftX)]);
+
+        let loaded = Bindings::parse(&toml::to_string(&bindings).unwrap()).unwrap();
+        assert_eq!(loaded.actions, bindings.actions);
+    }
+
+    #[test]
+    fn missing_actions_keep_their_defaults() {
+        let loaded = Bindings::parse("Use = [\"key:F1\"]\nFire = []").unwrap();
+        assert_eq!(loaded.get(Action::Use), [Binding::Key(Keycode::F1)]);
+        assert_eq!(loaded.get(Action::Fire), []);
+        assert_eq!(loaded.get(Action::MoveForward), Bindings::default().get(Action::MoveForward));
+    }
+
+    #[test]
+    fn malformed_bindings_are_rejected() {
+        assert!("w".parse::<Binding>().is_err());
+        assert!("pedal:left".parse::<Binding>().is_err());
+        assert!("mouse:x3".parse::<Binding>().is_err());
+        assert!("axis:throttle".parse::<Binding>().is_err());
+    }
+}
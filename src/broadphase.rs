@@ -0,0 +1,158 @@
+use nalgebra::Vector2;
+
+use crate::collision::{Segment, Segments};
+use crate::map::cell_index;
+
+/// Uniform grid over the map's cells, listing the segments that touch each cell, so collision
+/// queries only look at segments near the circle instead of every segment in the level
+pub struct SegmentGrid {
+    pub width: u32,
+    pub height: u32,
+    pub cell_size: f32,
+    pub segments: Vec<Segment>,
+    /// Indices into `segments` for each cell, row by row
+    cells: Vec<Vec<usize>>
+}
+
+impl SegmentGrid {
+    /// Creates an empty grid with the same cells as a map of the given size
+    pub fn new(width: u32, height: u32, cell_size: f32) -> Self {
+        Self {
+            width, height, cell_size,
+            segments: Vec::new(),
+            cells: vec![Vec::new(); (width * height) as usize]
+        }
+    }
+
+    /// Replaces the segments in the grid
+    pub fn rebuild(&mut self, segments: &[Segment]) {
+        self.segments = segments.to_vec();
+        self.cells.iter_mut().for_each(Vec::clear);
+
+        for (i, &(a, b)) in segments.iter().enumerate() {
+            let (x0, y0, x1, y1) = self.cell_range(a.inf(&b), a.sup(&b));
+            for y in y0..=y1 {
+                for x in x0..=x1 {
+                    self.cells[(y * self.width + x) as usize].push(i);
+                }
+            }
+        }
+    }
+
+    /// Range of cells overlapping the box from `min` to `max`. Anything past the edge of the grid
+    /// belongs to the cells along that edge, so nothing is lost outside it
+    fn cell_range(&self, min: Vector2<f32>, max: Vector2<f32>) -> (u32, u32, u32, u32) {
+        let clamp_x = |x: f32| cell_index(x, self.cell_size).clamp(0, self.width as i32 - 1) as u32;
+        let clamp_y = |y: f32| cell_index(y, self.cell_size).clamp(0, self.height as i32 - 1) as u32;
+        (clamp_x(min.x), clamp_y(min.y), clamp_x(max.x), clamp_y(max.y))
+    }
+}
+
+impl Segments for SegmentGrid {
+    fn near<F: FnMut(&Segment)>(&self, pos: Vector2<f32>, r: f32, mut f: F) {
+        if self.width == 0 || self.height == 0 {
+            return;
+        }
+
+        // A segment spanning several of the cells is visited once for each, which doesn't
+        // change which collision is deepest
+        let offset = Vector2::new(r, r);
+        let (x0, y0, x1, y1) = self.cell_range(pos - offset, pos + offset);
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                for &i in self.cells[(y * self.width + x) as usize].iter() {
+                    f(&self.segments[i]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collision::collide_with_segments;
+    use crate::map::{CellType, Map};
+    use proptest::prelude::*;
+
+    fn v(x: f32, y: f32) -> Vector2<f32> {
+        Vector2::new(x, y)
+    }
+
+    fn visited(grid: &SegmentGrid, pos: Vector2<f32>, r: f32) -> Vec<Segment> {
+        let mut segments = Vec::new();
+        grid.near(pos, r, |&segment| {
+            if !segments.contains(&segment) {
+                segments.push(segment);
+            }
+        });
+        segments
+    }
+
+    #[test]
+    fn queries_only_visit_nearby_cells() {
+        let mut grid = SegmentGrid::new(10, 10, 1.0);
+        let near = (v(2.0, 2.0), v(2.0, 3.0));
+        let far = (v(8.0, 8.0), v(9.0, 8.0));
+        grid.rebuild(&[near, far]);
+
+        assert_eq!(visited(&grid, v(1.9, 2.5), 0.25), [near]);
+        assert_eq!(visited(&grid, v(8.5, 7.9), 0.25), [far]);
+        assert!(visited(&grid, v(5.5, 5.5), 0.25).is_empty());
+    }
+
+    #[test]
+    fn long_segments_are_found_from_every_cell_they_cross() {
+        let mut grid = SegmentGrid::new(10, 10, 1.0);
+        let long = (v(0.5, 4.0), v(9.5, 4.0));
+        grid.rebuild(&[long]);
+
+        for x in 0..10 {
+            assert_eq!(visited(&grid, v(x as f32 + 0.5, 3.8), 0.25), [long]);
+        }
+    }
+
+    #[test]
+    fn segments_outside_the_grid_are_kept_at_its_edge() {
+        let mut grid = SegmentGrid::new(4, 4, 1.0);
+        let outside = (v(-3.0, 1.0), v(-3.0, 2.0));
+        grid.rebuild(&[outside]);
+
+        assert_eq!(visited(&grid, v(-2.9, 1.5), 0.25), [outside]);
+    }
+
+    /// A map with walls around the edge and scattered inside, one bit of `walls` per inner cell
+    fn scattered(walls: u64) -> Map {
+        let mut map = Map::new(10, 10, 1.0);
+        for y in 0..10 {
+            for x in 0..10 {
+                let edge = x == 0 || y == 0 || x == 9 || y == 9;
+                let inner = (y * 10 + x) % 64;
+                map.set(x, y, (edge || walls & (1 << inner) != 0) as CellType);
+            }
+        }
+        map.regenerate_segments();
+        map
+    }
+
+    proptest! {
+        #[test]
+        fn grid_finds_the_same_collisions_as_brute_force(
+            walls in any::<u64>(),
+            x in -1.0f32..11.0, y in -1.0f32..11.0,
+            r in 0.05f32..1.5
+        ) {
+            let map = scattered(walls);
+            let pos = v(x, y);
+
+            let brute = collide_with_segments(pos, r, &map.segments);
+            let grid = collide_with_segments(pos, r, &map.segment_grid);
+            match (brute, grid) {
+                (None, None) => (),
+                (Some(brute), Some(grid)) => prop_assert!((brute.depth - grid.depth).abs() < 1e-6),
+                (brute, grid) => prop_assert!(false, "brute force found {:?}, grid {:?}",
+                    brute.map(|c| c.depth), grid.map(|c| c.depth))
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use sdl2::image::SaveSurface;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::surface::Surface;
+use serde::Deserialize;
+
+use crate::player::{lerp_angle, Player};
+use crate::renderer::Renderer;
+
+/// Saves the renderer's current frame as a PNG
+pub fn save_png<P: AsRef<Path>>(renderer: &Renderer, path: P) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    let mut bytes = renderer.bytes();
+    let surface = Surface::from_data(&mut bytes, renderer.width, renderer.height, renderer.pitch() as u32, PixelFormatEnum::RGBA8888)
+        .map_err(anyhow::Error::msg)?;
+
+    surface.save(path)
+        .map_err(anyhow::Error::msg)
+        .with_context(|| format!("failed to save {}", path.display()))
+}
+
+/// Returns the first `dir/screenshot_NNNN.png` that doesn't exist yet, creating `dir` if needed
+pub fn next_screenshot_path<P: AsRef<Path>>(dir: P) -> anyhow::Result<PathBuf> {
+    let dir = dir.as_ref();
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("failed to create {}", dir.display()))?;
+
+    (1..10000)
+        .map(|n| dir.join(format!("screenshot_{:04}.png", n)))
+        .find(|path| !path.exists())
+        .ok_or_else(|| anyhow!("{} is full of screenshots", dir.display()))
+}
+
+/// Path of frame `index` of a sequence saved into `dir`
+pub fn sequence_frame_path<P: AsRef<Path>>(dir: P, index: usize) -> PathBuf {
+    dir.as_ref().join(format!("frame_{:05}.png", index))
+}
+
+/// A point the camera passes through
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct Keyframe {
+    pub position: (f32, f32),
+    /// Facing angle in degrees, clockwise from +x
+    #[serde(default)]
+    pub facing: f32,
+    /// Screen heights, see `Player::pitch`
+    #[serde(default)]
+    pub pitch: f32
+}
+
+/// A scripted camera path, moving through its keyframes at a constant rate
+#[derive(Deserialize, Clone, Debug)]
+pub struct CameraPath {
+    pub keyframes: Vec<Keyframe>
+}
+
+impl CameraPath {
+    pub fn parse(string: &str) -> anyhow::Result<Self> {
+        let path: Self = toml::from_str(string).map_err(|e| anyhow!("{}", e))?;
+        if path.keyframes.is_empty() {
+            return Err(anyhow!("camera path has no keyframes"));
+        }
+        Ok(path)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let string = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read camera path {}", path.display()))?;
+
+        Self::parse(&string)
+            .with_context(|| format!("failed to parse camera path {}", path.display()))
+    }
+
+    /// Places `player` along the path, from the first keyframe at `t` 0.0 to the last at 1.0
+    pub fn place(&self, player: &mut Player, t: f32) {
+        let last = self.keyframes.len() - 1;
+        let along = t.clamp(0.0, 1.0) * last as f32;
+        let i = (along.floor() as usize).min(last.saturating_sub(1));
+        let a = self.keyframes[i];
+        let b = self.keyframes[(i + 1).min(last)];
+        let f = along - i as f32;
+
+        player.position.0 = a.position.0 + (b.position.0 - a.position.0) * f;
+        player.position.1 = a.position.1 + (b.position.1 - a.position.1) * f;
+        player.pitch = a.pitch + (b.pitch - a.pitch) * f;
+
+        player.set_facing(lerp_angle(a.facing.to_radians(), b.facing.to_radians(), f));
+    }
+}
@@ -1,108 +1,5 @@
 use nalgebra::Vector2;
-
-// // https://ericleong.me/research/circle-line/#collision-response
-// pub fn lines_intersect(
-//     x1: f32, y1: f32, x2: f32, y2: f32,
-//     x3: f32, y3: f32, x4: f32, y4: f32
-// ) -> Option<Vector2<f32>> {
-//     let a1 = y2 - y1;
-//     let b1 = x1 - x2;
-//     let c1 = a1 * x1 + b1 * y1;
-//     let a2 = y4 - y3;
-//     let b2 = x3 - x4;
-//     let c2 = a2 * x3 + b2 * y3;
-//     let det = a1 * b2 - a2 * b1;
-//     if det != 0.0 {
-//         let x = (b2 * c1 - b1 * c2) / det;
-//         let y = (a1 * c2 - a2 * c1) / det;
-//         if x >= x1.min(x2) && x <= x1.max(x2)
-//         && x >= x3.min(x4) && x <= x3.max(x4)
-//         && y >= y1.min(y2) && y <= y1.max(y2)
-//         && y >= y3.min(y4) && y <= y3.max(y4) {
-//             return Some(Vector2::new(x, y));
-//         }
-//     }
-
-//     None
-// }
-
-// pub fn closest_point_on_line(
-//     lx1: f32, ly1: f32, lx2: f32, ly2: f32,
-//     x0: f32, y0: f32
-// ) -> Vector2<f32> {
-//     let a1 = ly2 - ly1;
-//     let b1 = lx1 - lx2;
-//     let c1 = (ly2 - ly1) * lx1 + (lx1 - lx2) * ly1;
-//     let c2 = -b1 * x0 + a1 * y0;
-//     let det = a1 * a1 - -b1 * b1;
-//     let cx;
-//     let cy;
-
-//     if det != 0.0 {
-//         cx = (a1 * c1 - b1 * c2) / det;
-//         cy = (a1 * c2 - -b1 * c1) / det;
-//     } else {
-//         cx = y0;
-//         cy = y0;
-//     }
-
-//     return Vector2::new(cx, cy);
-// }
-
-// pub fn collide_circle_segment(
-//     circle: Vector2<f32>, r: f32,
-//     line_0: Vector2<f32>, line_1: Vector2<f32>,
-//     circle_vel: Vector2<f32> 
-// ) -> Option<Vector2<f32>> {
-//     let point1 = closest_point_on_line(
-//         line_0.x, line_0.y, line_1.x, line_1.y,
-//         circle.x, circle.y
-//     );
-
-//     let movement_intersection = lines_intersect(
-//         circle.x, circle.y, circle.x + circle_vel.x, circle.y + circle_vel.y,
-//         line_0.x, line_0.y, line_1.x, line_1.y
-//     );
-
-//     if let Some(movement_intersection) = movement_intersection {
-//         dbg!("movement intersection");
-
-//         // b
-//         // closest point on line from endpoint of movement vector
-//         let closest_movement_line = closest_point_on_line(
-//             line_0.x, line_0.y, line_1.x, line_1.y,
-//             circle.x + circle_vel.x, circle.y + circle_vel.y
-//         );
-
-//         // c
-//         // closest point on movement vector to line_0
-//         let closest_line0_movement = closest_point_on_line(
-//             circle.x, circle.y, circle.x + circle_vel.x, circle.y + circle_vel.y,
-//             line_0.x, line_0.y
-//         );
-
-//         // d
-//         // closest point on movement vector to line_1
-//         let closest_line1_movement = closest_point_on_line(
-//             circle.x, circle.y, circle.x + circle_vel.x, circle.y + circle_vel.y,
-//             line_1.x, line_1.y
-//         );
-
-//         // TODO: you might need more checks
-//         if closest_movement_line.metric_distance(&(circle + circle_vel)) < r
-//             && closest_line0_movement.metric_distance(&line_0) < r
-//             && closest_line1_movement.metric_distance(&line_1) < r {
-//             let stopped_position = movement_intersection - r * 
-//                 ((circle - movement_intersection).magnitude() / (circle - point1).magnitude()) *
-//                 circle_vel.normalize();
-
-//             // TODO: Check for collisions with endpoints
-//             return Some(stopped_position);
-//         }
-//     }
-
-//     None
-// }
+use serde::Deserialize;
 
 // http://code.alaiwan.org/blog/collision-disk.html
 pub struct CollisionResult {
@@ -135,7 +32,19 @@ pub fn collide_circle_with_segment(circle: Vector2<f32>, r: f32, s0: Vector2<f32
     }
 
     let dist = delta.magnitude();
-    let normal = delta * (1.0 / dist);
+    let normal = if dist > 0.0 {
+        delta * (1.0 / dist)
+    } else {
+        // The center is exactly on the segment, so there is no direction to push out along.
+        // Use the segment's left side, which is the outside of the squares `Map` builds,
+        // or any direction if the segment is a point
+        let tangent = s1 - s0;
+        if tangent.magnitude_squared() > 0.0 {
+            Vector2::new(-tangent.y, tangent.x).normalize()
+        } else {
+            Vector2::new(1.0, 0.0)
+        }
+    };
 
     Some(CollisionResult {
         depth: r - dist,
@@ -143,10 +52,38 @@ pub fn collide_circle_with_segment(circle: Vector2<f32>, r: f32, s0: Vector2<f32
     })
 }
 
-pub fn collide_with_segments(pos: Vector2<f32>, r: f32, segments: &[(Vector2<f32>, Vector2<f32>)]) -> Option<CollisionResult> {
+/// A wall segment, from its first point to its second
+pub type Segment = (Vector2<f32>, Vector2<f32>);
+
+/// A set of segments collision queries can be run against
+pub trait Segments {
+    /// Calls `f` with every segment that might be within `r` of `pos`, and possibly others
+    fn near<F: FnMut(&Segment)>(&self, pos: Vector2<f32>, r: f32, f: F);
+}
+
+/// A plain list of segments, every one of which is tested
+impl Segments for [Segment] {
+    fn near<F: FnMut(&Segment)>(&self, _pos: Vector2<f32>, _r: f32, f: F) {
+        self.iter().for_each(f);
+    }
+}
+
+impl Segments for Vec<Segment> {
+    fn near<F: FnMut(&Segment)>(&self, pos: Vector2<f32>, r: f32, f: F) {
+        self.as_slice().near(pos, r, f);
+    }
+}
+
+impl<const N: usize> Segments for [Segment; N] {
+    fn near<F: FnMut(&Segment)>(&self, pos: Vector2<f32>, r: f32, f: F) {
+        self.as_slice().near(pos, r, f);
+    }
+}
+
+pub fn collide_with_segments<S: Segments + ?Sized>(pos: Vector2<f32>, r: f32, segments: &S) -> Option<CollisionResult> {
     let mut earliest_collision: Option<CollisionResult> = None;
 
-    for segment in segments.iter() {
+    segments.near(pos, r, |segment| {
         if let Some(collision) = collide_circle_with_segment(pos, r, segment.0, segment.1) {
             if let Some(ref mut earliest) = earliest_collision {
                 if collision.depth > earliest.depth {
@@ -156,16 +93,111 @@ pub fn collide_with_segments(pos: Vector2<f32>, r: f32, segments: &[(Vector2<f32
                 earliest_collision = Some(collision);
             }
         }
-    }
+    });
 
     earliest_collision
 }
 
-const COLLISION_RESOLUTION: usize = 5;
+/// Where a moving circle first touches something
+pub struct SweepResult {
+    /// Fraction of the movement made before touching, from 0 to 1
+    pub time: f32,
+    /// Direction from the contact point to the circle's center
+    pub normal: Vector2<f32>
+}
+
+/// Time of impact of a circle moving towards a point, or 0 if it already overlaps the point
+/// and is moving further in
+fn sweep_circle_point(pos: Vector2<f32>, r: f32, vel: Vector2<f32>, point: Vector2<f32>) -> Option<SweepResult> {
+    let m = pos - point;
+    let b = m.dot(&vel);
+    if b >= 0.0 {
+        return None;
+    }
+
+    let c = m.dot(&m) - r * r;
+    let time = if c <= 0.0 {
+        0.0
+    } else {
+        // |m + vel t| = r, taking the first of the two roots
+        let a = vel.dot(&vel);
+        let discriminant = b * b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        (-b - discriminant.sqrt()) / a
+    };
+
+    if time > 1.0 {
+        return None;
+    }
+
+    let contact = m + vel * time;
+    let length = contact.magnitude();
+    Some(SweepResult {
+        time,
+        normal: if length > 0.0 { contact / length } else { -vel.normalize() }
+    })
+}
+
+/// Time of impact of a circle moving along `vel` with a segment, if it touches it during the move.
+/// A circle already overlapping the segment and moving further into it hits at time 0, and one
+/// whose center is on the segment is left to `collide_circle_with_segment` to push out
+pub fn sweep_circle_with_segment(pos: Vector2<f32>, r: f32, vel: Vector2<f32>, s0: Vector2<f32>, s1: Vector2<f32>) -> Option<SweepResult> {
+    if vel.dot(&vel) == 0.0 {
+        return None;
+    }
+
+    let tangent = s1 - s0;
+    let length_squared = tangent.dot(&tangent);
+
+    // The flat side facing the circle, between the two ends
+    if length_squared > 0.0 {
+        let mut normal = Vector2::new(-tangent.y, tangent.x) / length_squared.sqrt();
+        let mut distance = (pos - s0).dot(&normal);
+        if distance < 0.0 {
+            normal = -normal;
+            distance = -distance;
+        }
+
+        let approach = -vel.dot(&normal);
+        if distance > 0.0 && approach > 0.0 {
+            let time = ((distance - r) / approach).max(0.0);
+            let along = (pos + vel * time - s0).dot(&tangent) / length_squared;
+            if time <= 1.0 && (0.0..=1.0).contains(&along) {
+                return Some(SweepResult { time, normal });
+            }
+        }
+    }
+
+    // Otherwise it can only hit one of the ends first
+    match (sweep_circle_point(pos, r, vel, s0), sweep_circle_point(pos, r, vel, s1)) {
+        (Some(a), Some(b)) => Some(if a.time <= b.time { a } else { b }),
+        (a, b) => a.or(b)
+    }
+}
+
+/// Earliest contact of a circle moving along `vel` with any of the segments
+pub fn sweep_segments<S: Segments + ?Sized>(pos: Vector2<f32>, r: f32, vel: Vector2<f32>, segments: &S) -> Option<SweepResult> {
+    let mut earliest: Option<SweepResult> = None;
 
-pub fn slide_move(mut pos: Vector2<f32>, r: f32, vel: Vector2<f32>, segments: &[(Vector2<f32>, Vector2<f32>)]) -> Vector2<f32> {
-    pos += vel;
+    // Everything the circle can touch during the move is within this of the middle of it
+    let reach = r + vel.magnitude() * 0.5;
+    segments.near(pos + vel * 0.5, reach, |segment| {
+        if let Some(sweep) = sweep_circle_with_segment(pos, r, vel, segment.0, segment.1) {
+            if earliest.as_ref().is_none_or(|earliest| sweep.time < earliest.time) {
+                earliest = Some(sweep);
+            }
+        }
+    });
+
+    earliest
+}
+
+const COLLISION_RESOLUTION: usize = 5;
 
+/// Pushes a circle out of any segments it overlaps
+fn depenetrate<S: Segments + ?Sized>(mut pos: Vector2<f32>, r: f32, segments: &S) -> Vector2<f32> {
     for _ in 0..COLLISION_RESOLUTION {
         if let Some(collision) = collide_with_segments(pos, r, segments) {
             pos += collision.normal * collision.depth;
@@ -175,4 +207,420 @@ pub fn slide_move(mut pos: Vector2<f32>, r: f32, vel: Vector2<f32>, segments: &[
     }
 
     pos
-}
\ No newline at end of file
+}
+
+/// Moves a circle by `vel` and then pushes it out of the walls. Cheap, but a move longer than
+/// the circle's radius can pass straight through a thin wall, see `sweep_move`
+pub fn slide_move<S: Segments + ?Sized>(pos: Vector2<f32>, r: f32, vel: Vector2<f32>, segments: &S) -> Vector2<f32> {
+    depenetrate(pos + vel, r, segments)
+}
+
+/// How a body interacts with other bodies
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Solidity {
+    /// Pushes and is pushed by other solid bodies
+    Solid,
+    /// Reports what overlaps it without pushing anything
+    Trigger,
+    /// Passes through everything
+    #[default]
+    None
+}
+
+/// A moving circle, like the player or a pushable object
+#[derive(Clone, Copy, Debug)]
+pub struct Body {
+    pub position: Vector2<f32>,
+    pub radius: f32,
+    /// How hard the body is to push. Two overlapping bodies move apart in inverse proportion to
+    /// their masses, and an infinite mass never moves
+    pub mass: f32,
+    pub solidity: Solidity
+}
+
+impl Body {
+    fn inverse_mass(&self) -> f32 {
+        if self.mass.is_finite() { 1.0 / self.mass } else { 0.0 }
+    }
+}
+
+/// Collision of circle `a` with circle `b`, with the normal pointing from `b` towards `a`
+pub fn collide_circles(a: Vector2<f32>, ra: f32, b: Vector2<f32>, rb: f32) -> Option<CollisionResult> {
+    let delta = a - b;
+    let r = ra + rb;
+    if delta.dot(&delta) >= r * r {
+        return None;
+    }
+
+    let dist = delta.magnitude();
+    Some(CollisionResult {
+        depth: r - dist,
+        // Any direction will do for two circles on top of each other
+        normal: if dist > 0.0 { delta / dist } else { Vector2::new(1.0, 0.0) }
+    })
+}
+
+/// Pushes overlapping solid bodies apart, sharing each push by mass, and pushes the bodies that
+/// moved back out of the walls in the same loop so one push can't leave a body in a wall.
+/// Returns the pairs of a trigger and a solid body that overlap afterwards, lower index first.
+/// Every pair is tested, which is fine for the handful of bodies a level has
+pub fn resolve_bodies<S: Segments + ?Sized>(bodies: &mut [Body], segments: &S) -> Vec<(usize, usize)> {
+    for _ in 0..COLLISION_RESOLUTION {
+        let mut moved = vec![false; bodies.len()];
+
+        for i in 0..bodies.len() {
+            for j in i + 1..bodies.len() {
+                let (a, b) = (bodies[i], bodies[j]);
+                if a.solidity != Solidity::Solid || b.solidity != Solidity::Solid {
+                    continue;
+                }
+
+                let total = a.inverse_mass() + b.inverse_mass();
+                if total == 0.0 {
+                    continue;
+                }
+
+                if let Some(collision) = collide_circles(a.position, a.radius, b.position, b.radius) {
+                    let push = collision.normal * collision.depth / total;
+                    bodies[i].position += push * a.inverse_mass();
+                    bodies[j].position -= push * b.inverse_mass();
+                    moved[i] |= a.inverse_mass() > 0.0;
+                    moved[j] |= b.inverse_mass() > 0.0;
+                }
+            }
+        }
+
+        if !moved.contains(&true) {
+            break;
+        }
+
+        for (body, _) in bodies.iter_mut().zip(moved).filter(|&(_, moved)| moved) {
+            body.position = depenetrate(body.position, body.radius, segments);
+        }
+    }
+
+    let mut touching = Vec::new();
+    for i in 0..bodies.len() {
+        for j in i + 1..bodies.len() {
+            let (a, b) = (bodies[i], bodies[j]);
+            let pair = (a.solidity, b.solidity);
+            if (pair == (Solidity::Trigger, Solidity::Solid) || pair == (Solidity::Solid, Solidity::Trigger))
+                && collide_circles(a.position, a.radius, b.position, b.radius).is_some() {
+                touching.push((i, j));
+            }
+        }
+    }
+
+    touching
+}
+
+/// Moves a circle by `vel`, stopping at the first wall in the way and sliding along it with
+/// what is left of the move. Never tunnels, however fast the circle is
+pub fn sweep_move<S: Segments + ?Sized>(mut pos: Vector2<f32>, r: f32, vel: Vector2<f32>, segments: &S) -> Vector2<f32> {
+    let mut remaining = vel;
+
+    for _ in 0..COLLISION_RESOLUTION {
+        let Some(sweep) = sweep_segments(pos, r, remaining, segments) else {
+            pos += remaining;
+            break;
+        };
+
+        pos += remaining * sweep.time;
+        remaining *= 1.0 - sweep.time;
+        remaining -= sweep.normal * remaining.dot(&sweep.normal);
+    }
+
+    // Stopping exactly at the contact leaves rounding errors either way
+    depenetrate(pos, r, segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    use crate::map::Map;
+    use proptest::prelude::*;
+    use proptest::test_runner::TestCaseError;
+
+    const ROOM: &str = "\
+11111
+10001
+10101
+10001
+11111";
+
+    fn v(x: f32, y: f32) -> Vector2<f32> {
+        Vector2::new(x, y)
+    }
+
+    /// Distance from `pos` to the nearest segment
+    fn clearance(pos: Vector2<f32>, segments: &[(Vector2<f32>, Vector2<f32>)]) -> f32 {
+        segments.iter()
+            .map(|&(s0, s1)| (pos - closest_point_on_segment(pos, s0, s1)).magnitude())
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    #[test]
+    fn closest_point_clamps_to_the_segment() {
+        let (s0, s1) = (v(0.0, 0.0), v(4.0, 0.0));
+        assert_eq!(closest_point_on_segment(v(1.0, 3.0), s0, s1), v(1.0, 0.0));
+        assert_eq!(closest_point_on_segment(v(-2.0, 1.0), s0, s1), s0);
+        assert_eq!(closest_point_on_segment(v(7.0, -1.0), s0, s1), s1);
+        assert_eq!(closest_point_on_segment(v(2.0, 0.0), s0, s1), v(2.0, 0.0));
+    }
+
+    #[test]
+    fn closest_point_on_a_point_segment_is_the_point() {
+        let p = v(1.0, 1.0);
+        assert_eq!(closest_point_on_segment(v(5.0, -3.0), p, p), p);
+    }
+
+    #[test]
+    fn circle_collision_depth_and_normal() {
+        let (s0, s1) = (v(0.0, 0.0), v(4.0, 0.0));
+        assert!(collide_circle_with_segment(v(2.0, 1.5), 1.0, s0, s1).is_none());
+
+        let collision = collide_circle_with_segment(v(2.0, 0.75), 1.0, s0, s1).unwrap();
+        assert!((collision.depth - 0.25).abs() < 1e-6);
+        assert_eq!(collision.normal, v(0.0, 1.0));
+
+        // Past the end the circle is pushed away from the end point
+        let collision = collide_circle_with_segment(v(4.6, 0.8), 1.0, s0, s1).unwrap();
+        assert!((collision.normal - v(0.6, 0.8)).magnitude() < 1e-6);
+    }
+
+    #[test]
+    fn circle_centered_on_a_segment_gets_a_usable_normal() {
+        let collision = collide_circle_with_segment(v(2.0, 0.0), 0.5, v(0.0, 0.0), v(4.0, 0.0)).unwrap();
+        assert_eq!(collision.depth, 0.5);
+        assert!((collision.normal.magnitude() - 1.0).abs() < 1e-6);
+
+        let collision = collide_circle_with_segment(v(1.0, 1.0), 0.5, v(1.0, 1.0), v(1.0, 1.0)).unwrap();
+        assert!(collision.normal.x.is_finite() && collision.normal.y.is_finite());
+    }
+
+    #[test]
+    fn slide_move_stops_at_walls_and_slides_along_them() {
+        let wall = [(v(0.0, 0.0), v(0.0, 10.0))];
+
+        // Straight into the wall stops one radius away
+        let pos = slide_move(v(1.0, 5.0), 0.5, v(-0.75, 0.0), &wall);
+        assert!((pos - v(0.5, 5.0)).magnitude() < 1e-5, "{:?}", pos);
+
+        // At an angle only the movement into the wall is lost
+        let pos = slide_move(v(1.0, 5.0), 0.5, v(-0.75, 0.5), &wall);
+        assert!((pos - v(0.5, 5.5)).magnitude() < 1e-5, "{:?}", pos);
+
+        // Moving away isn't affected
+        let pos = slide_move(v(1.0, 5.0), 0.5, v(0.5, 0.25), &wall);
+        assert_eq!(pos, v(1.5, 5.25));
+    }
+
+    #[test]
+    fn slide_move_into_a_corner_stays_out_of_both_walls() {
+        let map = Map::parse(ROOM, 1.0).unwrap();
+        let (x, y) = map.cell_origin(1, 1);
+        let pos = slide_move(v(x + 0.5, y + 0.5), 0.25, v(-0.4, -0.4), &map.segments);
+
+        assert!((pos - v(x + 0.25, y + 0.25)).magnitude() < 1e-4, "{:?}", pos);
+    }
+
+    #[test]
+    fn sweep_hits_the_side_facing_the_circle() {
+        let (s0, s1) = (v(0.0, 0.0), v(0.0, 10.0));
+
+        let sweep = sweep_circle_with_segment(v(2.0, 5.0), 0.5, v(-3.0, 0.0), s0, s1).unwrap();
+        assert!((sweep.time - 0.5).abs() < 1e-6);
+        assert_eq!(sweep.normal, v(1.0, 0.0));
+
+        // From the other side, too short, and moving away
+        let sweep = sweep_circle_with_segment(v(-2.0, 5.0), 0.5, v(3.0, 0.0), s0, s1).unwrap();
+        assert_eq!(sweep.normal, v(-1.0, 0.0));
+        assert!(sweep_circle_with_segment(v(2.0, 5.0), 0.5, v(-1.0, 0.0), s0, s1).is_none());
+        assert!(sweep_circle_with_segment(v(2.0, 5.0), 0.5, v(1.0, 0.0), s0, s1).is_none());
+    }
+
+    #[test]
+    fn sweep_hits_the_ends() {
+        let (s0, s1) = (v(0.0, 0.0), v(0.0, 10.0));
+
+        // Coming straight down at the top end touches it a radius above
+        let sweep = sweep_circle_with_segment(v(0.0, -2.0), 0.5, v(0.0, 2.0), s0, s1).unwrap();
+        assert!((sweep.time - 0.75).abs() < 1e-6);
+        assert!((sweep.normal - v(0.0, -1.0)).magnitude() < 1e-6);
+
+        // Passing just past the end misses it
+        assert!(sweep_circle_with_segment(v(0.6, -2.0), 0.5, v(0.0, 4.0), s0, s1).is_none());
+    }
+
+    #[test]
+    fn sweep_starting_in_contact_hits_at_once() {
+        let (s0, s1) = (v(0.0, 0.0), v(0.0, 10.0));
+
+        let sweep = sweep_circle_with_segment(v(0.4, 5.0), 0.5, v(-1.0, 0.0), s0, s1).unwrap();
+        assert_eq!(sweep.time, 0.0);
+        assert_eq!(sweep.normal, v(1.0, 0.0));
+
+        // Moving out is fine
+        assert!(sweep_circle_with_segment(v(0.4, 5.0), 0.5, v(1.0, 0.0), s0, s1).is_none());
+    }
+
+    #[test]
+    fn sweep_move_does_not_tunnel_through_thin_walls() {
+        let wall = [(v(0.0, 0.0), v(0.0, 10.0))];
+
+        // A move several times the radius ends up on the far side with slide_move
+        let tunneled = slide_move(v(1.0, 5.0), 0.25, v(-3.0, 0.0), &wall);
+        assert!(tunneled.x < 0.0);
+
+        let pos = sweep_move(v(1.0, 5.0), 0.25, v(-3.0, 0.0), &wall);
+        assert!((pos - v(0.25, 5.0)).magnitude() < 1e-5, "{:?}", pos);
+
+        // And slides along it with the rest of the move
+        let pos = sweep_move(v(1.0, 5.0), 0.25, v(-3.0, 2.0), &wall);
+        assert!((pos - v(0.25, 7.0)).magnitude() < 1e-5, "{:?}", pos);
+    }
+
+    #[test]
+    fn sweep_move_into_a_corner_stays_out_of_both_walls() {
+        let map = Map::parse(ROOM, 1.0).unwrap();
+        let (x, y) = map.cell_origin(1, 1);
+        let pos = sweep_move(v(x + 0.5, y + 0.5), 0.25, v(-4.0, -4.0), &map.segments);
+
+        assert!((pos - v(x + 0.25, y + 0.25)).magnitude() < 1e-4, "{:?}", pos);
+    }
+
+    fn body(x: f32, y: f32, mass: f32, solidity: Solidity) -> Body {
+        Body { position: v(x, y), radius: 0.5, mass, solidity }
+    }
+
+    #[test]
+    fn circle_collision_points_from_the_other_circle() {
+        assert!(collide_circles(v(0.0, 0.0), 0.5, v(1.0, 0.0), 0.5).is_none());
+
+        let collision = collide_circles(v(0.0, 0.0), 0.5, v(0.6, 0.0), 0.5).unwrap();
+        assert!((collision.depth - 0.4).abs() < 1e-6);
+        assert_eq!(collision.normal, v(-1.0, 0.0));
+
+        let collision = collide_circles(v(1.0, 1.0), 0.5, v(1.0, 1.0), 0.5).unwrap();
+        assert_eq!(collision.depth, 1.0);
+        assert!((collision.normal.magnitude() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn bodies_are_pushed_apart_by_mass() {
+        let no_walls: [Segment; 0] = [];
+
+        // Equal masses share the push
+        let mut bodies = [body(0.0, 0.0, 1.0, Solidity::Solid), body(0.5, 0.0, 1.0, Solidity::Solid)];
+        resolve_bodies(&mut bodies, &no_walls);
+        assert!((bodies[0].position - v(-0.25, 0.0)).magnitude() < 1e-6);
+        assert!((bodies[1].position - v(0.75, 0.0)).magnitude() < 1e-6);
+
+        // Three times the mass moves a third as far
+        let mut bodies = [body(0.0, 0.0, 3.0, Solidity::Solid), body(0.6, 0.0, 1.0, Solidity::Solid)];
+        resolve_bodies(&mut bodies, &no_walls);
+        assert!((bodies[0].position - v(-0.1, 0.0)).magnitude() < 1e-6);
+        assert!((bodies[1].position - v(0.9, 0.0)).magnitude() < 1e-6);
+
+        // An infinite mass doesn't move at all, and two of them are left alone
+        let mut bodies = [body(0.0, 0.0, f32::INFINITY, Solidity::Solid), body(0.6, 0.0, 1.0, Solidity::Solid)];
+        resolve_bodies(&mut bodies, &no_walls);
+        assert_eq!(bodies[0].position, v(0.0, 0.0));
+        assert!((bodies[1].position - v(1.0, 0.0)).magnitude() < 1e-6);
+
+        let mut bodies = [body(0.0, 0.0, f32::INFINITY, Solidity::Solid), body(0.6, 0.0, f32::INFINITY, Solidity::Solid)];
+        resolve_bodies(&mut bodies, &no_walls);
+        assert_eq!(bodies[1].position, v(0.6, 0.0));
+    }
+
+    #[test]
+    fn triggers_report_overlaps_without_pushing() {
+        let no_walls: [Segment; 0] = [];
+        let mut bodies = [
+            body(0.0, 0.0, 1.0, Solidity::Solid),
+            body(0.5, 0.0, 1.0, Solidity::Trigger),
+            body(0.0, 0.5, 1.0, Solidity::None),
+            body(5.0, 0.0, 1.0, Solidity::Trigger)
+        ];
+
+        let touching = resolve_bodies(&mut bodies, &no_walls);
+        assert_eq!(touching, vec![(0, 1)]);
+        assert_eq!(bodies[0].position, v(0.0, 0.0));
+        assert_eq!(bodies[1].position, v(0.5, 0.0));
+        assert_eq!(bodies[2].position, v(0.0, 0.5));
+    }
+
+    #[test]
+    fn bodies_pushed_against_a_wall_stay_out_of_it() {
+        // Pushing a light body into the wall leaves it against the wall and pushes back the other
+        let wall = [(v(0.0, 10.0), v(0.0, 0.0))];
+        let mut bodies = [body(0.5, 5.0, 1.0, Solidity::Solid), body(1.2, 5.0, 1.0, Solidity::Solid)];
+        resolve_bodies(&mut bodies, &wall);
+
+        assert!(clearance(bodies[0].position, &wall) >= 0.5 - 1e-4, "{:?}", bodies[0].position);
+        assert!((bodies[1].position - bodies[0].position).magnitude() >= 1.0 - 1e-2, "{:?}", bodies);
+    }
+
+    /// Radius of the circles moved from `start`
+    const START_RADIUS: f32 = 0.25;
+
+    /// A position anywhere in an empty cell of `ROOM`, at least `START_RADIUS` from its sides
+    fn start() -> impl Strategy<Value = Vector2<f32>> {
+        (any::<prop::sample::Index>(), 0.25f32..0.75, 0.25f32..0.75).prop_map(|(cell, fx, fy)| {
+            let map = Map::parse(ROOM, 1.0).unwrap();
+            let (x, y) = map.empty_cell(cell);
+            let (ox, oy) = map.cell_origin(x, y);
+            v(ox + fx, oy + fy)
+        })
+    }
+
+    /// Fails unless a circle at `pos` is in an empty cell and doesn't overlap any wall
+    fn check_clear_of_walls(map: &Map, pos: Vector2<f32>, r: f32) -> Result<(), TestCaseError> {
+        prop_assert!(pos.x.is_finite() && pos.y.is_finite());
+        prop_assert!(clearance(pos, &map.segments) >= r - 1e-4, "{:?} overlaps a wall", pos);
+        let cell = map.cell_at(pos.x, pos.y);
+        prop_assert!(cell.is_some_and(|(x, y)| map.get(x, y) == 0), "{:?} ended in a wall", pos);
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn closest_point_is_on_the_segment_and_closest(
+            px in -10.0f32..10.0, py in -10.0f32..10.0,
+            ax in -10.0f32..10.0, ay in -10.0f32..10.0,
+            bx in -10.0f32..10.0, by in -10.0f32..10.0
+        ) {
+            let (pos, s0, s1) = (v(px, py), v(ax, ay), v(bx, by));
+            let closest = closest_point_on_segment(pos, s0, s1);
+
+            // On the segment: the distances to both ends add up to its length
+            let length = (s1 - s0).magnitude();
+            prop_assert!(((closest - s0).magnitude() + (closest - s1).magnitude() - length).abs() < 1e-3);
+
+            // No point along the segment is closer
+            let distance = (pos - closest).magnitude();
+            for i in 0..=20 {
+                let other = s0 + (s1 - s0) * (i as f32 / 20.0);
+                prop_assert!(distance <= (pos - other).magnitude() + 1e-3);
+            }
+        }
+
+        #[test]
+        fn slide_move_never_ends_inside_a_wall(start in start(), angle in -PI..PI, speed in 0.0f32..0.25) {
+            let map = Map::parse(ROOM, 1.0).unwrap();
+            let pos = slide_move(start, START_RADIUS, v(angle.cos(), angle.sin()) * speed, &map.segments);
+            check_clear_of_walls(&map, pos, START_RADIUS)?;
+        }
+
+        #[test]
+        fn sweep_move_never_passes_through_a_wall(start in start(), angle in -PI..PI, speed in 0.0f32..10.0) {
+            // However fast, the circle stays in the room and out of the pillar
+            let map = Map::parse(ROOM, 1.0).unwrap();
+            let pos = sweep_move(start, START_RADIUS, v(angle.cos(), angle.sin()) * speed, &map.segments);
+            check_clear_of_walls(&map, pos, START_RADIUS)?;
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+
+use crate::input::TickInput;
+use crate::map::Map;
+use crate::player::Player;
+use crate::settings::Settings;
+use crate::simulation;
+
+const MAGIC: &[u8; 4] = b"RTDM";
+const VERSION: u8 = 2;
+/// Demos from before the look settings were stored, which replay with the defaults
+const VERSION_WITHOUT_SETTINGS: u8 = 1;
+
+const INVERT_MOUSE: u8 = 1 << 0;
+const INVERT_PITCH: u8 = 1 << 1;
+
+// Each frame starts with a byte of flags saying which fields follow. Actions and sticks are only
+// written when they change, mouse movement and newly pressed actions only when there are any,
+// so a tick where nothing changes takes a single byte
+const PRESSED: u8 = 1 << 0;
+const JUST_PRESSED: u8 = 1 << 1;
+const MOUSE: u8 = 1 << 2;
+const LEFT_STICK: u8 = 1 << 3;
+const RIGHT_STICK: u8 = 1 << 4;
+
+/// Where the player ended up at the end of a recording, used to check that a replay matches
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DemoEnd {
+    pub position: (f32, f32),
+    pub facing: f32,
+    pub pitch: f32
+}
+
+impl DemoEnd {
+    pub fn new(player: &Player) -> Self {
+        Self {
+            position: player.position,
+            facing: player.facing,
+            pitch: player.pitch
+        }
+    }
+}
+
+/// A recording of the input of every simulation tick, starting from a level's spawn point
+#[derive(Clone, PartialEq, Debug)]
+pub struct Demo {
+    /// Path of the level the demo was recorded in
+    pub level: String,
+    pub ticks_per_second: f32,
+    /// Look settings of the player who recorded it, since they decide what mouse movement does
+    pub settings: Settings,
+    pub frames: Vec<TickInput>,
+    pub end: Option<DemoEnd>
+}
+
+impl Demo {
+    pub fn new(level: &str, ticks_per_second: f32, settings: Settings) -> Self {
+        Self {
+            level: level.to_string(),
+            ticks_per_second,
+            settings,
+            frames: Vec::new(),
+            end: None
+        }
+    }
+
+    /// Adds the input of the next tick
+    pub fn record(&mut self, input: TickInput) {
+        self.frames.push(input);
+    }
+
+    /// Stores where `player` ended up, so replays can be checked against it
+    pub fn finish(&mut self, player: &Player) {
+        self.end = Some(DemoEnd::new(player));
+    }
+
+    /// Runs every recorded tick on `map` and `player`, which should be fresh from the demo's level,
+    /// with the settings the demo was recorded with
+    pub fn replay(&self, map: &mut Map, player: &mut Player) {
+        self.settings.apply(player);
+        let dt = 1.0 / self.ticks_per_second;
+        for input in self.frames.iter() {
+            simulation::step(map, player, input, dt);
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&self.ticks_per_second.to_le_bytes());
+        bytes.extend_from_slice(&(self.level.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(self.level.as_bytes());
+        bytes.extend_from_slice(&self.settings.yaw_sensitivity.to_le_bytes());
+        bytes.extend_from_slice(&self.settings.pitch_sensitivity.to_le_bytes());
+        let mut invert = 0;
+        if self.settings.invert_mouse { invert |= INVERT_MOUSE; }
+        if self.settings.invert_pitch { invert |= INVERT_PITCH; }
+        bytes.push(invert);
+        bytes.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+
+        let mut previous = TickInput::default();
+        for frame in self.frames.iter() {
+            let mut flags = 0;
+            if frame.pressed != previous.pressed { flags |= PRESSED; }
+            if frame.just_pressed != 0 { flags |= JUST_PRESSED; }
+            if frame.mouse_delta != (0.0, 0.0) { flags |= MOUSE; }
+            if frame.left_stick != previous.left_stick { flags |= LEFT_STICK; }
+            if frame.right_stick != previous.right_stick { flags |= RIGHT_STICK; }
+
+            bytes.push(flags);
+            if flags & PRESSED != 0 { bytes.extend_from_slice(&frame.pressed.to_le_bytes()); }
+            if flags & JUST_PRESSED != 0 { bytes.extend_from_slice(&frame.just_pressed.to_le_bytes()); }
+            if flags & MOUSE != 0 { write_pair(&mut bytes, frame.mouse_delta); }
+            if flags & LEFT_STICK != 0 { write_pair(&mut bytes, frame.left_stick); }
+            if flags & RIGHT_STICK != 0 { write_pair(&mut bytes, frame.right_stick); }
+
+            previous = *frame;
+        }
+
+        match self.end {
+            Some(end) => {
+                bytes.push(1);
+                write_pair(&mut bytes, end.position);
+                bytes.extend_from_slice(&end.facing.to_le_bytes());
+                bytes.extend_from_slice(&end.pitch.to_le_bytes());
+            }
+            None => bytes.push(0)
+        }
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader { bytes, pos: 0 };
+
+        if reader.take(4)? != MAGIC {
+            return Err(anyhow!("not a demo file"));
+        }
+        let version = reader.u8()?;
+        if version != VERSION && version != VERSION_WITHOUT_SETTINGS {
+            return Err(anyhow!("unsupported demo version {}, expected {}", version, VERSION));
+        }
+
+        let ticks_per_second = reader.f32()?;
+        let level_len = reader.u16()? as usize;
+        let level = String::from_utf8(reader.take(level_len)?.to_vec())
+            .context("level path is not valid UTF-8")?;
+
+        let settings = if version == VERSION_WITHOUT_SETTINGS {
+            Settings::default()
+        } else {
+            let yaw_sensitivity = reader.f32()?;
+            let pitch_sensitivity = reader.f32()?;
+            let invert = reader.u8()?;
+            Settings {
+                yaw_sensitivity,
+                pitch_sensitivity,
+                invert_mouse: invert & INVERT_MOUSE != 0,
+                invert_pitch: invert & INVERT_PITCH != 0
+            }
+        };
+
+        let frame_count = reader.u32()? as usize;
+        let mut frames = Vec::with_capacity(frame_count.min(bytes.len()));
+        let mut previous = TickInput::default();
+        for _ in 0..frame_count {
+            let flags = reader.u8()?;
+            let frame = TickInput {
+                pressed: if flags & PRESSED != 0 { reader.u16()? } else { previous.pressed },
+                just_pressed: if flags & JUST_PRESSED != 0 { reader.u16()? } else { 0 },
+                mouse_delta: if flags & MOUSE != 0 { reader.pair()? } else { (0.0, 0.0) },
+                left_stick: if flags & LEFT_STICK != 0 { reader.pair()? } else { previous.left_stick },
+                right_stick: if flags & RIGHT_STICK != 0 { reader.pair()? } else { previous.right_stick }
+            };
+            frames.push(frame);
+            previous = frame;
+        }
+
+        let end = match reader.u8()? {
+            0 => None,
+            _ => Some(DemoEnd {
+                position: reader.pair()?,
+                facing: reader.f32()?,
+                pitch: reader.f32()?
+            })
+        };
+
+        if reader.pos != bytes.len() {
+            return Err(anyhow!("{} unexpected bytes after the end of the demo", bytes.len() - reader.pos));
+        }
+
+        Ok(Self { level, ticks_per_second, settings, frames, end })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("failed to read demo {}", path.display()))?;
+
+        Self::from_bytes(&bytes)
+            .with_context(|| format!("failed to parse demo {}", path.display()))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_bytes())
+            .with_context(|| format!("failed to write demo {}", path.display()))
+    }
+}
+
+fn write_pair(bytes: &mut Vec<u8>, (a, b): (f32, f32)) {
+    bytes.extend_from_slice(&a.to_le_bytes());
+    bytes.extend_from_slice(&b.to_le_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> anyhow::Result<&'a [u8]> {
+        let slice = self.bytes.get(self.pos..self.pos + n)
+            .ok_or_else(|| anyhow!("demo ends unexpectedly at byte {}", self.bytes.len()))?;
+        self.pos += n;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> anyhow::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn f32(&mut self) -> anyhow::Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn pair(&mut self) -> anyhow::Result<(f32, f32)> {
+        Ok((self.f32()?, self.f32()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bindings::Action;
+
+    fn sample() -> Demo {
+        let settings = Settings { yaw_sensitivity: 0.002, invert_pitch: true, ..Settings::default() };
+        let mut demo = Demo::new("res/levels/default.toml", 60.0, settings);
+        let walk = TickInput { pressed: Action::MoveForward.bit(), ..Default::default() };
+
+        demo.record(TickInput::default());
+        demo.record(TickInput { just_pressed: Action::MoveForward.bit(), ..walk });
+        for _ in 0..10 {
+            demo.record(walk);
+        }
+        demo.record(TickInput { mouse_delta: (3.0, -1.5), right_stick: (0.25, 0.0), ..walk });
+        demo.record(TickInput { left_stick: (-0.5, 0.125), right_stick: (0.25, 0.0), ..Default::default() });
+        demo.end = Some(DemoEnd { position: (1.5, 2.5), facing: 0.75, pitch: -0.1 });
+        demo
+    }
+
+    #[test]
+    fn round_trip() {
+        let demo = sample();
+        assert_eq!(Demo::from_bytes(&demo.to_bytes()).unwrap(), demo);
+
+        let mut unfinished = sample();
+        unfinished.end = None;
+        assert_eq!(Demo::from_bytes(&unfinished.to_bytes()).unwrap(), unfinished);
+    }
+
+    #[test]
+    fn unchanged_ticks_take_one_byte() {
+        let mut demo = sample();
+        let before = demo.to_bytes().len();
+        let last = *demo.frames.last().unwrap();
+        for _ in 0..100 {
+            demo.record(last);
+        }
+
+        assert_eq!(demo.to_bytes().len(), before + 100);
+    }
+
+    #[test]
+    fn older_demos_replay_with_default_settings() {
+        let mut demo = sample();
+        let mut bytes = demo.to_bytes();
+
+        // Version 1 had nothing between the level path and the frame count
+        let settings_start = 4 + 1 + 4 + 2 + demo.level.len();
+        bytes.drain(settings_start..settings_start + 9);
+        bytes[4] = VERSION_WITHOUT_SETTINGS;
+
+        demo.settings = Settings::default();
+        assert_eq!(Demo::from_bytes(&bytes).unwrap(), demo);
+    }
+
+    #[test]
+    fn rejects_truncated_and_foreign_files() {
+        let bytes = sample().to_bytes();
+        for len in 0..bytes.len() {
+            assert!(Demo::from_bytes(&bytes[..len]).is_err(), "accepted {} of {} bytes", len, bytes.len());
+        }
+        assert!(Demo::from_bytes(b"PNG\0 not a demo").is_err());
+    }
+}
@@ -0,0 +1,168 @@
+/// Seconds a door takes to fully open or close
+pub const DOOR_SLIDE_TIME: f32 = 1.0;
+/// Seconds a door stays open before closing on its own
+pub const DOOR_CLOSE_DELAY: f32 = 3.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DoorState {
+    Closed,
+    Opening,
+    Open,
+    Closing
+}
+
+/// A thin wall across the middle of a cell that slides open when used
+#[derive(Clone, Copy, Debug)]
+pub struct Door {
+    pub state: DoorState,
+    /// How far the door has slid open, from 0.0 (closed) to 1.0 (open)
+    pub open: f32,
+    /// Seconds left before an open door starts closing
+    pub timer: f32,
+    /// True if the door runs along the x axis, false if it runs along the y axis
+    pub horizontal: bool
+}
+
+impl Door {
+    pub fn new(horizontal: bool) -> Self {
+        Self {
+            state: DoorState::Closed,
+            open: 0.0,
+            timer: 0.0,
+            horizontal
+        }
+    }
+
+    /// Starts opening the door, or keeps it open for longer if it already is
+    pub fn activate(&mut self) {
+        match self.state {
+            DoorState::Closed | DoorState::Closing => self.state = DoorState::Opening,
+            DoorState::Open => self.timer = DOOR_CLOSE_DELAY,
+            DoorState::Opening => ()
+        }
+    }
+
+    /// Returns true if the door does not block anything
+    pub fn is_open(&self) -> bool {
+        self.open >= 1.0
+    }
+
+    /// Advances the door by `dt` seconds. A door will not start closing while `blocked` is true
+    pub fn update(&mut self, dt: f32, blocked: bool) {
+        match self.state {
+            DoorState::Closed => (),
+            DoorState::Opening => {
+                self.open = (self.open + dt / DOOR_SLIDE_TIME).min(1.0);
+                if self.open >= 1.0 {
+                    self.state = DoorState::Open;
+                    self.timer = DOOR_CLOSE_DELAY;
+                }
+            }
+            DoorState::Open => {
+                self.timer -= dt;
+                if self.timer <= 0.0 && !blocked {
+                    self.state = DoorState::Closing;
+                }
+            }
+            DoorState::Closing if blocked => self.state = DoorState::Opening,
+            DoorState::Closing => {
+                self.open = (self.open - dt / DOOR_SLIDE_TIME).max(0.0);
+                if self.open <= 0.0 {
+                    self.state = DoorState::Closed;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Updates the door in steps of a tenth of a second
+    fn run(door: &mut Door, seconds: f32, blocked: bool) {
+        for _ in 0..(seconds * 10.0).round() as usize {
+            door.update(0.1, blocked);
+        }
+    }
+
+    #[test]
+    fn closed_doors_stay_closed_until_used() {
+        let mut door = Door::new(true);
+        run(&mut door, 5.0, false);
+        assert_eq!(door.state, DoorState::Closed);
+        assert_eq!(door.open, 0.0);
+    }
+
+    #[test]
+    fn used_doors_open_then_close_on_their_own() {
+        let mut door = Door::new(true);
+        door.activate();
+        assert_eq!(door.state, DoorState::Opening);
+
+        run(&mut door, DOOR_SLIDE_TIME / 2.0, false);
+        assert_eq!(door.state, DoorState::Opening);
+        assert!((door.open - 0.5).abs() < 1e-4 && !door.is_open());
+
+        run(&mut door, DOOR_SLIDE_TIME / 2.0, false);
+        assert_eq!(door.state, DoorState::Open);
+        assert!(door.is_open());
+
+        // Open for the whole delay, then closing
+        run(&mut door, DOOR_CLOSE_DELAY - 0.2, false);
+        assert_eq!(door.state, DoorState::Open);
+        run(&mut door, 0.3, false);
+        assert_eq!(door.state, DoorState::Closing);
+
+        run(&mut door, DOOR_SLIDE_TIME + 0.1, false);
+        assert_eq!(door.state, DoorState::Closed);
+        assert_eq!(door.open, 0.0);
+    }
+
+    #[test]
+    fn blocked_doors_stay_open() {
+        let mut door = Door::new(false);
+        door.activate();
+        run(&mut door, DOOR_SLIDE_TIME + DOOR_CLOSE_DELAY + 1.0, true);
+        assert_eq!(door.state, DoorState::Open);
+
+        // Closes once whatever was in the way leaves
+        door.update(0.1, false);
+        assert_eq!(door.state, DoorState::Closing);
+    }
+
+    #[test]
+    fn closing_doors_reopen_when_used_or_blocked() {
+        let mut door = Door::new(true);
+        door.activate();
+        run(&mut door, DOOR_SLIDE_TIME + DOOR_CLOSE_DELAY + 0.5, false);
+        assert_eq!(door.state, DoorState::Closing);
+        let open = door.open;
+        assert!(open > 0.0 && open < 1.0);
+
+        door.activate();
+        assert_eq!(door.state, DoorState::Opening);
+        door.update(0.1, false);
+        assert!(door.open > open, "reopening starts from where it was");
+
+        // Something walking into a closing door sends it back open too
+        while door.state != DoorState::Closing {
+            door.update(0.1, false);
+        }
+        door.update(0.1, true);
+        assert_eq!(door.state, DoorState::Opening);
+    }
+
+    #[test]
+    fn using_an_open_door_keeps_it_open_longer() {
+        let mut door = Door::new(true);
+        door.activate();
+        run(&mut door, DOOR_SLIDE_TIME + DOOR_CLOSE_DELAY - 0.5, false);
+        assert_eq!(door.state, DoorState::Open);
+
+        door.activate();
+        assert_eq!(door.timer, DOOR_CLOSE_DELAY);
+        run(&mut door, DOOR_CLOSE_DELAY - 0.5, false);
+        assert_eq!(door.state, DoorState::Open);
+    }
+}
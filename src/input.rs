@@ -1,6 +1,13 @@
 use std::collections::HashMap;
 
+use sdl2::controller::{Axis, Button};
 use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+
+use crate::bindings::{Action, Binding, Bindings};
+
+/// How far a trigger has to be pulled before bindings to it count as pressed
+pub const TRIGGER_THRESHOLD: f32 = 0.5;
 
 // from yume
 #[derive(Clone, Copy)]
@@ -10,24 +17,99 @@ pub enum KeyState {
     Released
 }
 
+/// Shapes raw analog stick positions into something comfortable to steer with
+#[derive(Clone, Copy, Debug)]
+pub struct StickSettings {
+    /// Fraction of the stick's travel around the center that is ignored
+    pub dead_zone: f32,
+    /// Exponent of the response curve. 1.0 is linear, higher values give finer control near the center
+    pub exponent: f32
+}
+
+impl Default for StickSettings {
+    fn default() -> Self {
+        Self {
+            dead_zone: 0.2,
+            exponent: 2.0
+        }
+    }
+}
+
+impl StickSettings {
+    /// Applies a radial dead zone and the response curve to a stick position, keeping its direction.
+    /// The result has a length between 0.0 and 1.0
+    pub fn apply(&self, x: f32, y: f32) -> (f32, f32) {
+        let length = (x * x + y * y).sqrt();
+        if length <= self.dead_zone {
+            return (0.0, 0.0);
+        }
+
+        let magnitude = ((length.min(1.0) - self.dead_zone) / (1.0 - self.dead_zone)).powf(self.exponent);
+        (x / length * magnitude, y / length * magnitude)
+    }
+}
+
+/// Everything the simulation reads from the input during one tick. Unlike `Input` this is plain
+/// data, so it can be recorded and replayed to reproduce a run exactly
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct TickInput {
+    /// Set of pressed actions, one `Action::bit` each
+    pub pressed: u16,
+    /// Set of actions that have just been pressed
+    pub just_pressed: u16,
+    /// Relative mouse movement during the tick, in pixels
+    pub mouse_delta: (f32, f32),
+    /// Stick positions after the dead zone and response curve
+    pub left_stick: (f32, f32),
+    pub right_stick: (f32, f32)
+}
+
+impl TickInput {
+    /// Returns true if `action` is pressed
+    pub fn action_pressed(&self, action: Action) -> bool {
+        self.pressed & action.bit() != 0
+    }
+
+    /// Returns true if `action` has just been pressed
+    pub fn action_just_pressed(&self, action: Action) -> bool {
+        self.just_pressed & action.bit() != 0
+    }
+}
+
+#[derive(Default)]
 pub struct Input {
-    pub keys: HashMap<Keycode, KeyState>
+    pub keys: HashMap<Keycode, KeyState>,
+    pub mouse_buttons: HashMap<MouseButton, KeyState>,
+    pub controller_buttons: HashMap<Button, KeyState>,
+    /// Controller axis positions, from -1.0 to 1.0 for sticks and 0.0 to 1.0 for triggers
+    pub controller_axes: HashMap<Axis, f32>,
+    /// Axes treated as buttons, pressed while past `TRIGGER_THRESHOLD`
+    pub axis_buttons: HashMap<Axis, KeyState>,
+    pub bindings: Bindings,
+    pub stick_settings: StickSettings,
+    /// Relative mouse movement since the last update, in pixels
+    pub mouse_delta: (f32, f32)
 }
 
 #[allow(dead_code)]
 impl Input {
     pub fn new() -> Self {
-        Self {
-            keys: HashMap::new()
-        }
+        Self::default()
     }
 
     pub fn update(&mut self) {
-        for (_, v) in self.keys.iter_mut() {
+        let states = self.keys.values_mut()
+            .chain(self.mouse_buttons.values_mut())
+            .chain(self.controller_buttons.values_mut())
+            .chain(self.axis_buttons.values_mut());
+
+        for v in states {
             if let KeyState::JustPressed = *v {
                 *v = KeyState::Pressed;
             }
         }
+
+        self.mouse_delta = (0.0, 0.0);
     }
 
     /// Notify the input manager that a key has been pressed
@@ -59,4 +141,131 @@ impl Input {
     pub fn get_keystate(&self, key: Keycode) -> KeyState {
         *self.keys.get(&key).unwrap_or(&KeyState::Released)
     }
-}
\ No newline at end of file
+
+    /// Notify the input manager that a mouse button has been pressed
+    pub fn mouse_pressed(&mut self, button: MouseButton) {
+        self.mouse_buttons.insert(button, KeyState::JustPressed);
+    }
+
+    /// Notify the input manager that a mouse button has been released
+    pub fn mouse_released(&mut self, button: MouseButton) {
+        self.mouse_buttons.insert(button, KeyState::Released);
+    }
+
+    /// Notify the input manager that the mouse has moved by `dx`, `dy` pixels
+    pub fn mouse_moved(&mut self, dx: i32, dy: i32) {
+        self.mouse_delta.0 += dx as f32;
+        self.mouse_delta.1 += dy as f32;
+    }
+
+    /// Returns true if mouse `button` is pressed
+    pub fn get_mouse_pressed(&self, button: MouseButton) -> bool {
+        matches!(self.mouse_buttons.get(&button).unwrap_or(&KeyState::Released), KeyState::Pressed | KeyState::JustPressed)
+    }
+
+    /// Returns true if mouse `button` has just been pressed
+    pub fn get_mouse_just_pressed(&self, button: MouseButton) -> bool {
+        matches!(self.mouse_buttons.get(&button).unwrap_or(&KeyState::Released), KeyState::JustPressed)
+    }
+
+    /// Notify the input manager that a controller button has been pressed
+    pub fn controller_pressed(&mut self, button: Button) {
+        self.controller_buttons.insert(button, KeyState::JustPressed);
+    }
+
+    /// Notify the input manager that a controller button has been released
+    pub fn controller_released(&mut self, button: Button) {
+        self.controller_buttons.insert(button, KeyState::Released);
+    }
+
+    /// Notify the input manager that a controller axis has moved to `value`
+    pub fn controller_axis_moved(&mut self, axis: Axis, value: i16) {
+        let value = (value as f32 / i16::MAX as f32).max(-1.0);
+        self.controller_axes.insert(axis, value);
+
+        let held = value.abs() >= TRIGGER_THRESHOLD;
+        let state = self.axis_buttons.entry(axis).or_insert(KeyState::Released);
+        match (*state, held) {
+            (KeyState::Released, true) => *state = KeyState::JustPressed,
+            (KeyState::Pressed | KeyState::JustPressed, false) => *state = KeyState::Released,
+            _ => ()
+        }
+    }
+
+    /// Forgets all controller state, e.g. when the last controller is disconnected
+    pub fn controller_reset(&mut self) {
+        self.controller_buttons.clear();
+        self.controller_axes.clear();
+        self.axis_buttons.clear();
+    }
+
+    /// Returns the raw position of a controller axis
+    pub fn get_axis(&self, axis: Axis) -> f32 {
+        *self.controller_axes.get(&axis).unwrap_or(&0.0)
+    }
+
+    /// Returns the left stick position after the dead zone and response curve
+    pub fn left_stick(&self) -> (f32, f32) {
+        self.stick_settings.apply(self.get_axis(Axis::LeftX), self.get_axis(Axis::LeftY))
+    }
+
+    /// Returns the right stick position after the dead zone and response curve
+    pub fn right_stick(&self) -> (f32, f32) {
+        self.stick_settings.apply(self.get_axis(Axis::RightX), self.get_axis(Axis::RightY))
+    }
+
+    /// Returns the state of a bound input
+    pub fn get_binding_state(&self, binding: Binding) -> KeyState {
+        let state = match binding {
+            Binding::Key(key) => self.keys.get(&key),
+            Binding::Mouse(button) => self.mouse_buttons.get(&button),
+            Binding::Controller(button) => self.controller_buttons.get(&button),
+            Binding::Axis(axis) => self.axis_buttons.get(&axis)
+        };
+
+        *state.unwrap_or(&KeyState::Released)
+    }
+
+    /// Resolves the current state into what the simulation sees this tick
+    pub fn tick_input(&self) -> TickInput {
+        let mut tick = TickInput {
+            mouse_delta: self.mouse_delta,
+            left_stick: self.left_stick(),
+            right_stick: self.right_stick(),
+            ..Default::default()
+        };
+
+        for action in Action::ALL {
+            if self.action_pressed(action) {
+                tick.pressed |= action.bit();
+            }
+            if self.action_just_pressed(action) {
+                tick.just_pressed |= action.bit();
+            }
+        }
+
+        tick
+    }
+
+    /// Returns true if any input bound to `action` is pressed
+    pub fn action_pressed(&self, action: Action) -> bool {
+        self.bindings.get(action).iter()
+            .any(|&binding| matches!(self.get_binding_state(binding), KeyState::Pressed | KeyState::JustPressed))
+    }
+
+    /// Returns true if an input bound to `action` has just been pressed and no other already was
+    pub fn action_just_pressed(&self, action: Action) -> bool {
+        let states = self.bindings.get(action).iter().map(|&binding| self.get_binding_state(binding));
+        let mut just_pressed = false;
+
+        for state in states {
+            match state {
+                KeyState::Pressed => return false,
+                KeyState::JustPressed => just_pressed = true,
+                KeyState::Released => ()
+            }
+        }
+
+        just_pressed
+    }
+}
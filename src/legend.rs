@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+
+use crate::map::CellType;
+
+/// Maps the symbols of a grid to cell types
+#[derive(Clone, Debug, Default)]
+pub enum Legend {
+    /// Every cell is a number. Rows are read a digit at a time, or as whitespace separated
+    /// numbers if the first row contains whitespace, which allows values above 9.
+    #[default]
+    Numeric,
+    /// Every cell is one of the given symbols. If every symbol is a single character rows are
+    /// read a character at a time, so symbols may be spaces; otherwise rows are whitespace separated.
+    Symbols(HashMap<String, CellType>)
+}
+
+impl Legend {
+    /// Parses a grid, one row per line, into its width, height and cells
+    pub fn parse_grid(&self, string: &str) -> anyhow::Result<(u32, u32, Vec<CellType>)> {
+        // Trailing lines are dropped if they're empty, or blank in a numeric grid, where spaces
+        // can't be cells
+        let rows: Vec<&str> = string.lines().collect();
+        let rows = match rows.iter().rposition(|row| match self {
+            Legend::Numeric => !row.trim().is_empty(),
+            Legend::Symbols(_) => !row.is_empty()
+        }) {
+            Some(last) => &rows[..=last],
+            None => return Err(anyhow!("map is empty"))
+        };
+
+        let per_char = self.per_char(rows[0]);
+        let mut width = None;
+        let mut data = Vec::new();
+
+        for (y, row) in rows.iter().enumerate() {
+            let tokens = self.tokenize(row, per_char);
+            let row_width = *width.get_or_insert(tokens.len());
+
+            // A numeric grid is either a digit per cell or whitespace separated throughout
+            if let Legend::Numeric = self {
+                let trimmed = row.trim();
+                match trimmed.find(char::is_whitespace) {
+                    Some(x) if per_char => {
+                        let column = row.len() - row.trim_start().len() + x + 1;
+                        return Err(anyhow!("line {}, column {}: expected a digit per cell like line 1, found whitespace", y + 1, column));
+                    }
+                    None if !per_char => {
+                        return Err(anyhow!("line {}: expected whitespace separated numbers like line 1", y + 1));
+                    }
+                    _ => ()
+                }
+            }
+
+            if tokens.len() != row_width {
+                return Err(anyhow!("line {}: expected {} columns, found {}", y + 1, row_width, tokens.len()));
+            }
+
+            for (x, token) in tokens.iter().enumerate() {
+                let id = self.lookup(token)
+                    .ok_or_else(|| anyhow!("line {}, column {}: unknown symbol {:?}", y + 1, x + 1, token))?;
+                data.push(id);
+            }
+        }
+
+        Ok((width.unwrap_or(0) as u32, rows.len() as u32, data))
+    }
+
+    /// Whether the cells of a grid are single characters rather than whitespace separated.
+    /// Numeric grids decide this from their first row
+    fn per_char(&self, first_row: &str) -> bool {
+        match self {
+            Legend::Numeric => !first_row.trim().contains(char::is_whitespace),
+            Legend::Symbols(symbols) => symbols.keys().all(|symbol| symbol.chars().count() == 1)
+        }
+    }
+
+    fn tokenize<'a>(&self, row: &'a str, per_char: bool) -> Vec<&'a str> {
+        let row = match self {
+            Legend::Numeric => row.trim(),
+            Legend::Symbols(_) => row
+        };
+
+        if per_char {
+            row.char_indices().map(|(i, c)| &row[i..i + c.len_utf8()]).collect()
+        } else {
+            row.split_whitespace().collect()
+        }
+    }
+
+    fn lookup(&self, token: &str) -> Option<CellType> {
+        match self {
+            Legend::Numeric => token.parse().ok(),
+            Legend::Symbols(symbols) => symbols.get(token).copied()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbols(pairs: &[(&str, CellType)]) -> Legend {
+        Legend::Symbols(pairs.iter().map(|&(symbol, id)| (symbol.to_string(), id)).collect())
+    }
+
+    #[test]
+    fn unknown_symbols_name_their_line_and_column() {
+        let legend = symbols(&[(".", 0), ("#", 1)]);
+        let error = legend.parse_grid("###\n#.x\n###").unwrap_err();
+        assert_eq!(error.to_string(), "line 2, column 3: unknown symbol \"x\"");
+
+        let error = Legend::Numeric.parse_grid("111\n1a1").unwrap_err();
+        assert_eq!(error.to_string(), "line 2, column 2: unknown symbol \"a\"");
+    }
+
+    #[test]
+    fn ragged_rows_are_rejected() {
+        let error = Legend::Numeric.parse_grid("111\n10\n111").unwrap_err();
+        assert_eq!(error.to_string(), "line 2: expected 3 columns, found 2");
+
+        let error = symbols(&[(".", 0), ("#", 1)]).parse_grid("##\n#.#").unwrap_err();
+        assert_eq!(error.to_string(), "line 2: expected 2 columns, found 3");
+    }
+
+    #[test]
+    fn longer_symbols_are_separated_by_whitespace() {
+        let legend = symbols(&[("..", 0), ("wall", 1), ("glass", 2)]);
+        let (width, height, cells) = legend.parse_grid("wall wall  wall\nwall .. glass\n").unwrap();
+        assert_eq!((width, height), (3, 2));
+        assert_eq!(cells, vec![1, 1, 1, 1, 0, 2]);
+    }
+
+    #[test]
+    fn numbers_above_nine_are_separated_by_whitespace() {
+        let (width, height, cells) = Legend::Numeric.parse_grid("1 1 1\n12 0 10\n1 1 1").unwrap();
+        assert_eq!((width, height), (3, 3));
+        assert_eq!(cells[3..6], [12, 0, 10]);
+
+        // Without whitespace every digit is a cell
+        let (width, _, cells) = Legend::Numeric.parse_grid("121").unwrap();
+        assert_eq!(width, 3);
+        assert_eq!(cells, vec![1, 2, 1]);
+    }
+
+    #[test]
+    fn numeric_rows_are_all_read_the_same_way() {
+        let error = Legend::Numeric.parse_grid("111\n1 1 1\n111").unwrap_err();
+        assert_eq!(error.to_string(), "line 2, column 2: expected a digit per cell like line 1, found whitespace");
+
+        let error = Legend::Numeric.parse_grid("1 1 1\n111\n1 1 1").unwrap_err();
+        assert_eq!(error.to_string(), "line 2: expected whitespace separated numbers like line 1");
+    }
+
+    #[test]
+    fn trailing_blank_lines_are_dropped() {
+        let (_, height, _) = Legend::Numeric.parse_grid("111\n101\n111\n   \n\n").unwrap();
+        assert_eq!(height, 3);
+
+        let (_, height, _) = symbols(&[(".", 0), ("#", 1)]).parse_grid("###\n#.#\n###\n\n").unwrap();
+        assert_eq!(height, 3);
+    }
+
+    #[test]
+    fn spaces_can_be_symbols() {
+        let legend = symbols(&[(" ", 0), ("#", 1)]);
+        let (width, height, cells) = legend.parse_grid("###\n# #\n###").unwrap();
+        assert_eq!((width, height), (3, 3));
+        assert_eq!(cells[3..6], [1, 0, 1]);
+
+        // Trailing spaces are cells too, so dropping one makes the row short
+        let (_, _, cells) = legend.parse_grid("###\n#  \n###").unwrap();
+        assert_eq!(cells[3..6], [1, 0, 0]);
+        let error = legend.parse_grid("###\n# \n###").unwrap_err();
+        assert_eq!(error.to_string(), "line 2: expected 3 columns, found 2");
+
+        // Including a last row of only spaces
+        let (width, height, cells) = legend.parse_grid("# #\n# #\n   ").unwrap();
+        assert_eq!((width, height), (3, 3));
+        assert_eq!(cells[6..9], [0, 0, 0]);
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+use sdl2::pixels::Color;
+
+use crate::collision::Solidity;
+use crate::legend::Legend;
+use crate::map::{CellType, Map};
+use crate::player::Player;
+use crate::sprite::Sprite;
+use crate::texture::Texture;
+use crate::tile::TileDef;
+
+/// Where and which way the player starts
+#[derive(Deserialize, Clone, Copy)]
+pub struct Spawn {
+    pub position: (f32, f32),
+    /// Facing angle in degrees, clockwise from +x
+    #[serde(default)]
+    pub facing: f32
+}
+
+/// A sprite placed in a level
+#[derive(Deserialize, Clone, Copy)]
+pub struct SpriteDef {
+    pub position: (f32, f32),
+    /// Texture, using the same numbering as `LevelFile::grid`
+    pub texture: usize,
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+    /// Radius of the circle the sprite collides as
+    #[serde(default = "default_radius")]
+    pub radius: f32,
+    /// Leaving it out makes the sprite immovable
+    pub mass: Option<f32>,
+    /// `"solid"`, `"trigger"` or `"none"`, by default the sprite is only decoration
+    #[serde(default)]
+    pub solidity: Solidity
+}
+
+/// A tile type in a level. Textures are numbered from 1 in the order of `LevelFile::textures`
+#[derive(Deserialize, Clone, Copy)]
+pub struct TileFile {
+    /// Texture of every face that doesn't set its own
+    pub texture: usize,
+    pub north: Option<usize>,
+    pub south: Option<usize>,
+    pub east: Option<usize>,
+    pub west: Option<usize>,
+    #[serde(default = "default_solid")]
+    pub solid: bool,
+    #[serde(default)]
+    pub transparent: bool,
+    /// Whether cells of this tile are sliding doors
+    #[serde(default)]
+    pub door: bool
+}
+
+impl TileFile {
+    fn to_def(self) -> TileDef {
+        TileDef {
+            north: self.north.unwrap_or(self.texture) - 1,
+            south: self.south.unwrap_or(self.texture) - 1,
+            east: self.east.unwrap_or(self.texture) - 1,
+            west: self.west.unwrap_or(self.texture) - 1,
+            solid: self.solid,
+            transparent: self.transparent
+        }
+    }
+
+    fn textures(&self) -> [usize; 4] {
+        [self.north, self.south, self.east, self.west].map(|face| face.unwrap_or(self.texture))
+    }
+}
+
+/// On-disk description of a level
+#[derive(Deserialize)]
+pub struct LevelFile {
+    #[serde(default = "default_cell_size")]
+    pub cell_size: f32,
+    /// Grid of cells, one row per line. `0` is empty and `n` is `tiles[n - 1]`, or a
+    /// solid wall textured with `textures[n - 1]` if there are no tiles
+    pub grid: String,
+    /// Symbols used in `grid` and the cell each stands for. Without one the grid is numeric,
+    /// see `Legend::Numeric`
+    pub legend: Option<HashMap<String, CellType>>,
+    /// Optional numeric grid of per-cell floor textures. `0` falls back to the flat `floor` color,
+    /// `n` uses `textures[n - 1]`
+    pub floor_grid: Option<String>,
+    /// Optional grid of per-cell ceiling textures, like `floor_grid`
+    pub ceiling_grid: Option<String>,
+    /// Texture paths, relative to the level file
+    pub textures: Vec<PathBuf>,
+    pub floor: [u8; 3],
+    pub ceiling: [u8; 3],
+    pub spawn: Spawn,
+    #[serde(default)]
+    pub tiles: Vec<TileFile>,
+    #[serde(default)]
+    pub sprites: Vec<SpriteDef>
+}
+
+fn default_cell_size() -> f32 {
+    1.0
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+fn default_radius() -> f32 {
+    0.25
+}
+
+fn default_solid() -> bool {
+    true
+}
+
+pub struct Level {
+    pub map: Map,
+    pub spawn: Spawn
+}
+
+impl LevelFile {
+    pub fn parse(string: &str) -> anyhow::Result<Self> {
+        toml::from_str(string).map_err(|e| anyhow!("{}", e))
+    }
+
+    /// Builds the map described by this file, without loading any textures
+    pub fn build_map(&self) -> anyhow::Result<Map> {
+        let legend = match &self.legend {
+            Some(symbols) => Legend::Symbols(symbols.clone()),
+            None => Legend::Numeric
+        };
+
+        let mut map = Map::parse_with_legend(&self.grid, self.cell_size, &legend).context("invalid grid")?;
+        map.floor = Color::RGB(self.floor[0], self.floor[1], self.floor[2]);
+        map.ceiling = Color::RGB(self.ceiling[0], self.ceiling[1], self.ceiling[2]);
+
+        if let Some(grid) = &self.floor_grid {
+            map.parse_floors(grid).context("invalid floor grid")?;
+        }
+        if let Some(grid) = &self.ceiling_grid {
+            map.parse_ceilings(grid).context("invalid ceiling grid")?;
+        }
+
+        for (i, tile) in self.tiles.iter().enumerate() {
+            if let Some(&texture) = tile.textures().iter().find(|&&texture| texture == 0 || texture > self.textures.len()) {
+                return Err(anyhow!("tile {}: texture {} is not declared, expected 1 to {}", i + 1, texture, self.textures.len()));
+            }
+
+            map.tiles.push(tile.to_def());
+        }
+
+        if self.tiles.is_empty() {
+            self.check_ids(&map, "grid", Map::get, "texture", self.textures.len())?;
+        } else {
+            self.check_ids(&map, "grid", Map::get, "tile", self.tiles.len())?;
+        }
+        map.regenerate_segments();
+
+        for (i, tile) in self.tiles.iter().enumerate() {
+            if tile.door {
+                map.add_doors(i as CellType + 1);
+            }
+        }
+
+        self.check_textures(&map, "floor grid", Map::get_floor)?;
+        self.check_textures(&map, "ceiling grid", Map::get_ceiling)?;
+
+        for (i, sprite) in self.sprites.iter().enumerate() {
+            if sprite.texture == 0 || sprite.texture > self.textures.len() {
+                return Err(anyhow!("sprite {}: texture {} is not declared, expected 1 to {}", i + 1, sprite.texture, self.textures.len()));
+            }
+
+            if sprite.mass.is_some_and(|mass| mass.is_nan() || mass <= 0.0) {
+                return Err(anyhow!("sprite {}: mass has to be positive", i + 1));
+            }
+
+            map.sprites.push(Sprite {
+                position: sprite.position,
+                texture: sprite.texture - 1,
+                scale: sprite.scale,
+                radius: sprite.radius,
+                mass: sprite.mass.unwrap_or(f32::INFINITY),
+                solidity: sprite.solidity
+            });
+        }
+
+        Ok(map)
+    }
+
+    fn check_textures(&self, map: &Map, name: &str, get: impl Fn(&Map, u32, u32) -> CellType) -> anyhow::Result<()> {
+        self.check_ids(map, name, get, "texture", self.textures.len())
+    }
+
+    /// Checks that every cell of a grid refers to one of `count` declared `kind`s
+    fn check_ids(&self, map: &Map, name: &str, get: impl Fn(&Map, u32, u32) -> CellType, kind: &str, count: usize) -> anyhow::Result<()> {
+        for y in 0..map.height {
+            for x in 0..map.width {
+                let id = get(map, x, y);
+                if id as usize > count {
+                    return Err(anyhow!("{} line {}, column {}: {} {} is not declared, only {} are", name, y + 1, x + 1, kind, id, count));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Level {
+    /// Reads a level file, loading its textures relative to the file's directory
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let (file, mut map) = Self::read(path)?;
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for texture in file.textures.iter() {
+            let texture_path = dir.join(texture);
+            map.add_texture(Texture::from_file(&texture_path)
+                .with_context(|| format!("failed to load texture {}", texture_path.display()))?);
+        }
+
+        Ok(Self { map, spawn: file.spawn })
+    }
+
+    /// Reads a level file without loading its textures, for running the simulation without drawing it
+    pub fn load_untextured<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let (file, map) = Self::read(path.as_ref())?;
+        Ok(Self { map, spawn: file.spawn })
+    }
+
+    fn read(path: &Path) -> anyhow::Result<(LevelFile, Map)> {
+        let string = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read level {}", path.display()))?;
+
+        let file = LevelFile::parse(&string)
+            .with_context(|| format!("failed to parse level {}", path.display()))?;
+        let map = file.build_map()
+            .with_context(|| format!("failed to build level {}", path.display()))?;
+
+        Ok((file, map))
+    }
+
+    /// Creates a player at the spawn point
+    pub fn spawn_player(&self) -> Player {
+        let mut player = Player::new(self.spawn.position);
+        player.set_facing(self.spawn.facing.to_radians());
+        player
+    }
+}
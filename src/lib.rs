@@ -0,0 +1,18 @@
+pub mod map;
+pub mod input;
+pub mod player;
+pub mod texture;
+pub mod collision;
+pub mod broadphase;
+pub mod renderer;
+pub mod level;
+pub mod sprite;
+pub mod door;
+pub mod tile;
+pub mod legend;
+pub mod timestep;
+pub mod bindings;
+pub mod settings;
+pub mod simulation;
+pub mod demo;
+pub mod capture;
@@ -1,77 +1,238 @@
-use anyhow::{Context, Ok};
-use sdl2::{image::InitFlag, pixels::Color, rect::Rect, render::BlendMode, sys::{SDL_Delay, SDL_GetTicks}};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
-mod map;
-mod input;
-mod player;
-mod texture;
-mod collision;
+use anyhow::{anyhow, Ok};
+use sdl2::{image::InitFlag, keyboard::Keycode, pixels::PixelFormatEnum};
 
-const TICK_INTERVAL: u32 = 16;
+use ray_tracer::bindings::Bindings;
+use ray_tracer::capture::{self, CameraPath};
+use ray_tracer::demo::Demo;
+use ray_tracer::input::TickInput;
+use ray_tracer::settings::Settings;
+use ray_tracer::{input, level, map, player, renderer, simulation, timestep};
 
-const MAP: &str = include_str!("map.txt");
+const TICKS_PER_SECOND: f32 = 60.0;
 
-fn find_sdl_gl_driver() -> Option<u32> {
-    for (index, item) in sdl2::render::drivers().enumerate() {
-        if item.name == "opengl" {
-            return Some(index as u32);
+/// Shortest time a frame may take, so the loop doesn't spin where vsync isn't honored
+const MIN_FRAME_TIME: Duration = Duration::from_micros(1_000_000 / 144);
+
+const BINDINGS_PATH: &str = "bindings.toml";
+const SETTINGS_PATH: &str = "settings.toml";
+
+const DEFAULT_LEVEL: &str = "res/levels/default.toml";
+
+const SCREENSHOT_DIR: &str = "screenshots";
+
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 480;
+
+const USAGE: &str = "usage: ray_tracer [level] [--record <demo>] [--play <demo>]
+                  [--screenshot <png>] [--sequence <dir> [--frames <n>] [--camera <path>]]";
+
+#[derive(Default)]
+struct Args {
+    level: Option<String>,
+    /// Demo file to record the session into
+    record: Option<String>,
+    /// Demo file to play back, over and over
+    play: Option<String>,
+    /// Save a single frame here and exit, without opening a window
+    screenshot: Option<String>,
+    /// Save a numbered sequence of frames into this directory and exit
+    sequence: Option<String>,
+    /// Number of frames in the sequence
+    frames: Option<usize>,
+    /// Scripted camera path for the sequence
+    camera: Option<String>
+}
+
+fn value(option: &str, iter: &mut impl Iterator<Item = String>) -> anyhow::Result<String> {
+    iter.next().ok_or_else(|| anyhow!("{} needs a value\n{}", option, USAGE))
+}
+
+fn parse_args() -> anyhow::Result<Args> {
+    let mut args = Args::default();
+    let mut iter = std::env::args().skip(1);
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--record" => args.record = Some(value(&arg, &mut iter)?),
+            "--play" => args.play = Some(value(&arg, &mut iter)?),
+            "--screenshot" => args.screenshot = Some(value(&arg, &mut iter)?),
+            "--sequence" => args.sequence = Some(value(&arg, &mut iter)?),
+            "--camera" => args.camera = Some(value(&arg, &mut iter)?),
+            "--frames" => args.frames = Some(value(&arg, &mut iter)?.parse()
+                .map_err(|_| anyhow!("--frames needs a number of frames\n{}", USAGE))?),
+            _ if arg.starts_with("--") => return Err(anyhow!("unknown option {}\n{}", arg, USAGE)),
+            _ if args.level.is_none() => args.level = Some(arg),
+            _ => return Err(anyhow!("unexpected argument {}\n{}", arg, USAGE))
+        }
+    }
+
+    if args.record.is_some() && args.play.is_some() {
+        return Err(anyhow!("can't record and play a demo at the same time"));
+    }
+    if args.screenshot.is_some() && args.sequence.is_some() {
+        return Err(anyhow!("can't take a screenshot and a sequence at the same time"));
+    }
+    if args.camera.is_some() && args.play.is_some() {
+        return Err(anyhow!("can't follow a camera path and play a demo at the same time"));
+    }
+    if args.record.is_some() && (args.screenshot.is_some() || args.sequence.is_some()) {
+        return Err(anyhow!("can't record a demo while capturing without a window"));
+    }
+    if args.sequence.is_none() {
+        if args.frames.is_some() {
+            return Err(anyhow!("--frames only applies to --sequence\n{}", USAGE));
+        }
+        if args.camera.is_some() {
+            return Err(anyhow!("--camera only applies to --sequence\n{}", USAGE));
         }
     }
 
-    None
+    Ok(args)
 }
 
 fn main() -> anyhow::Result<()> {
+    let args = parse_args()?;
+
     let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
     let _image_context = sdl2::image::init(InitFlag::PNG | InitFlag::JPG);
+
+    let playback = args.play.as_deref().map(Demo::load).transpose()?;
+    let level_path = args.level.clone()
+        .or_else(|| playback.as_ref().map(|demo| demo.level.clone()))
+        .unwrap_or(DEFAULT_LEVEL.to_string());
+    let level = level::Level::load(&level_path)?;
+    let mut player = level.spawn_player();
+    let mut map = level.map;
+
+    if args.screenshot.is_some() || args.sequence.is_some() {
+        return capture_headless(&args, &mut map, &mut player, playback.as_ref());
+    }
+
+    let video_subsystem = sdl_context.video().unwrap();
+    let controller_subsystem = sdl_context.game_controller().unwrap();
+
     let window = video_subsystem
-        .window("ion know", 640, 480)
-        .opengl()
+        .window("ion know", WIDTH, HEIGHT)
         .position_centered()
         .build()?;
 
     let mut canvas = window
         .into_canvas()
-        .index(find_sdl_gl_driver().context("sdl gl driver")?)
-        .target_texture()
+        .present_vsync()
         .build()?;
 
     let (width, height) = canvas.window().size();
 
     let texture_creator = canvas.texture_creator();
-    
-    let map_width = MAP.split('\n').next().unwrap().len() - 1;
-    let map_height = MAP.len() / map_width - 1;
-    let mut map = map::Map::from_string(&MAP.replace("\r\n", ""), map_width as u32, map_height as u32, 1.0);
-    let mut player = player::Player::new((2.0, 2.0));
+    let mut frame = texture_creator.create_texture_streaming(PixelFormatEnum::RGBA8888, width, height)?;
+    let mut renderer = renderer::Renderer::new(width, height);
     let mut input = input::Input::new();
+    input.bindings = Bindings::load_or_create(BINDINGS_PATH)?;
+
+    // Demos play back with the settings they were recorded with
+    let settings = match &playback {
+        Some(demo) => demo.settings,
+        None => Settings::load_or_create(SETTINGS_PATH)?
+    };
+    settings.apply(&mut player);
 
     let mut events = sdl_context.event_pump().unwrap();
+    sdl_context.mouse().set_relative_mouse_mode(true);
 
-    let mut next_time = unsafe { SDL_GetTicks() } + TICK_INTERVAL;
+    // Open controllers by joystick index as they are plugged in, keyed by their instance id,
+    // which is what later events refer to them by. Controllers already connected at startup
+    // also get an added event
+    let mut controllers = HashMap::new();
 
-    map.add_texture(texture::Texture::from_file("res/textures/radial_gradient.png", &texture_creator)?);
-    map.add_texture(texture::Texture::from_file("res/textures/sky.png", &texture_creator)?);
-    map.add_texture(texture::Texture::from_file("res/textures/glassh.png", &texture_creator)?);
-    map.add_texture(texture::Texture::from_file("res/textures/warning.png", &texture_creator)?);
+    let ticks_per_second = playback.as_ref().map_or(TICKS_PER_SECOND, |demo| demo.ticks_per_second);
+    let mut recording = args.record.as_ref().map(|_| Demo::new(&level_path, ticks_per_second, settings));
+    let mut playback_tick = 0;
 
-    map.floor = Color::RGB(93, 63, 211);
-    map.ceiling = Color::GRAY;
+    let mut timestep = timestep::FixedTimestep::new(ticks_per_second);
+    let mut previous_player = player.clone();
+    let mut last_frame = Instant::now();
 
     'mainloop: loop {
         for event in events.poll_iter() {
             use sdl2::event::Event;
             match event {
                 Event::Quit { .. } => break 'mainloop,
-                Event::KeyDown { keycode: Some(keycode), repeat, .. } => {
-                    if !repeat {
-                        input.pressed(keycode);
+                // Give the cursor back, or capture it again
+                Event::KeyDown { keycode: Some(Keycode::Escape), repeat: false, .. } => {
+                    let mouse = sdl_context.mouse();
+                    let captured = !mouse.relative_mouse_mode();
+                    mouse.set_relative_mouse_mode(captured);
+                    // Don't turn the view by movement made just before letting go
+                    if !captured {
+                        input.mouse_delta = (0.0, 0.0);
+                    }
+                },
+                Event::KeyDown { keycode: Some(Keycode::F3), repeat: false, .. } => {
+                    renderer.debug_overlay = !renderer.debug_overlay;
+                },
+                // Switch to the old fisheye look, or back
+                Event::KeyDown { keycode: Some(Keycode::F4), repeat: false, .. } => {
+                    renderer.projection = match renderer.projection {
+                        renderer::Projection::Perspective => renderer::Projection::Fisheye,
+                        renderer::Projection::Fisheye => renderer::Projection::Perspective
+                    };
+                },
+                Event::KeyDown { keycode: Some(Keycode::F12), repeat: false, .. } => {
+                    // Failing to save shouldn't end the game, or lose a recording in progress
+                    let saved = capture::next_screenshot_path(SCREENSHOT_DIR)
+                        .and_then(|path| capture::save_png(&renderer, &path).map(|_| path));
+                    match saved {
+                        Result::Ok(path) => println!("Saved {}", path.display()),
+                        Err(e) => eprintln!("Failed to save screenshot: {:#}", e)
                     }
                 },
+                Event::KeyDown { keycode: Some(keycode), repeat: false, .. } => {
+                    input.pressed(keycode);
+                },
                 Event::KeyUp { keycode: Some(keycode), .. } => {
                     input.released(keycode);
                 }
+                // A released cursor is for the rest of the desktop, not for looking around
+                Event::MouseMotion { xrel, yrel, .. } if sdl_context.mouse().relative_mouse_mode() => {
+                    input.mouse_moved(xrel, yrel);
+                }
+                Event::MouseButtonDown { mouse_btn, .. } => {
+                    input.mouse_pressed(mouse_btn);
+                }
+                Event::MouseButtonUp { mouse_btn, .. } => {
+                    input.mouse_released(mouse_btn);
+                }
+                Event::ControllerDeviceAdded { which, .. } => {
+                    match controller_subsystem.open(which) {
+                        Result::Ok(controller) => {
+                            println!("Connected controller {}", controller.name());
+                            controllers.insert(controller.instance_id(), controller);
+                        }
+                        Err(e) => eprintln!("Failed to open controller {}: {}", which, e)
+                    }
+                }
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    if let Some(controller) = controllers.remove(&which) {
+                        println!("Disconnected controller {}", controller.name());
+                        // Input isn't kept per controller, so any still connected would lose
+                        // what they're holding too
+                        if controllers.is_empty() {
+                            input.controller_reset();
+                        }
+                    }
+                }
+                Event::ControllerButtonDown { button, .. } => {
+                    input.controller_pressed(button);
+                }
+                Event::ControllerButtonUp { button, .. } => {
+                    input.controller_released(button);
+                }
+                Event::ControllerAxisMotion { axis, value, .. } => {
+                    input.controller_axis_moved(axis, value);
+                }
                 _ => ()
             }
         }
@@ -79,88 +240,106 @@ fn main() -> anyhow::Result<()> {
         /////////////////
         // Update
         /////////////////
-        input.update();
-        player.update(&map, &input);
+        let now = Instant::now();
+        timestep.advance((now - last_frame).as_secs_f32());
+        last_frame = now;
 
-        /////////////////
-        // Render
-        /////////////////
-        canvas.set_draw_color(Color::RGBA(0, 0, 0, 255));
-        canvas.clear();
-
-        // Ceiling and floor
-        for i in 0..(height as usize) {
-            let color = if (i as u32) < height / 2 {
-                let shade = map.shade_from_height(height - (2 * i as u32), height);
-                Color::RGB((map.ceiling.r as f32 * shade) as u8, (map.ceiling.g as f32 * shade) as u8, (map.ceiling.b as f32 * shade) as u8)
-            } else {
-                let shade = map.shade_from_height(height - (2 * (height - (i as u32))), height);
-                Color::RGB((map.floor.r as f32 * shade) as u8, (map.floor.g as f32 * shade) as u8, (map.floor.b as f32 * shade) as u8)
+        while timestep.tick() {
+            let tick_input = match &playback {
+                Some(demo) => {
+                    // Start over from the spawn point once the demo runs out
+                    if playback_tick == demo.frames.len() {
+                        let level = level::Level::load(&level_path)?;
+                        player = level.spawn_player();
+                        settings.apply(&mut player);
+                        map = level.map;
+                        playback_tick = 0;
+                    }
+                    playback_tick += 1;
+                    demo.frames.get(playback_tick - 1).copied().unwrap_or_default()
+                }
+                None => input.tick_input()
             };
 
-            canvas.set_draw_color(color);
-            canvas.draw_line((0, i as i32), (width as i32, i as i32)).unwrap();
+            if let Some(demo) = &mut recording {
+                demo.record(tick_input);
+            }
+
+            previous_player = player.clone();
+            simulation::step(&mut map, &mut player, &tick_input, timestep.dt);
+            input.update();
         }
 
-        // Blend mode for shading
-        canvas.set_blend_mode(BlendMode::Blend);
+        /////////////////
+        // Render
+        /////////////////
+        renderer.render(&map, &player.interpolate(&previous_player, timestep.alpha()));
+        frame.update(None, &renderer.bytes(), renderer.pitch())?;
+        canvas.copy(&frame, None, None).map_err(anyhow::Error::msg)?;
 
-        let center = (height as f32) / 2.0;
+        canvas.present();
 
-        // Draw walls
-        for col in 0..width {
-            let angle = (player.facing - player.fov / 2.0) + (col as f32 / width as f32) * player.fov;
+        // With vsync working, presenting already waited for the display
+        let frame_time = last_frame.elapsed();
+        if frame_time < MIN_FRAME_TIME {
+            std::thread::sleep(MIN_FRAME_TIME - frame_time);
+        }
+    }
 
-            let raycast_result = map.cast_ray(
-                player.position.0, 
-                player.position.1, 
-                angle.cos(), 
-                angle.sin()
-            );
+    if let (Some(mut demo), Some(path)) = (recording, args.record) {
+        demo.finish(&player);
+        demo.save(&path)?;
+        println!("Recorded {} ticks to {}", demo.frames.len(), path);
+    }
 
-            if let Some(result) = raycast_result {
-                let distance = ((result.pos.0 - player.position.0).powf(2.0) + (result.pos.1 - player.position.1).powf(2.0)).sqrt();
-                let shade_amt = map.shade(distance);
+    Ok(())
+}
 
-                let line_height = if distance == 0.0 {
-                    height
-                } else {
-                    (height as f32 / distance).max(0.0).min(height as f32) as u32
-                };
+/// Renders frames without opening a window: a single screenshot of the spawn point or the end of
+/// a demo, or a sequence along a demo or camera path
+fn capture_headless(args: &Args, map: &mut map::Map, player: &mut player::Player, playback: Option<&Demo>) -> anyhow::Result<()> {
+    let mut renderer = renderer::Renderer::new(WIDTH, HEIGHT);
 
-                let shade = (0, 0, 0, 255 - ((shade_amt * 255.0) as u8));
-                let texture = &map.textures[map.get(result.cell.0, result.cell.1) as usize - 1];
+    if let Some(path) = &args.screenshot {
+        if let Some(demo) = playback {
+            demo.replay(map, player);
+        }
+        renderer.render(map, player);
+        capture::save_png(&renderer, path)?;
+        println!("Saved {}", path);
+        return Ok(());
+    }
 
-                assert!(line_height <= height);
+    let Some(dir) = &args.sequence else {
+        return Ok(());
+    };
+    std::fs::create_dir_all(dir)?;
+    if let Some(demo) = playback {
+        demo.settings.apply(player);
+    }
 
-                let start = (center - line_height as f32 / 2.0) as i32;
-                let src = Rect::new((texture.width as f32 * result.u) as i32, 0, 1, texture.height);
-                let dst = Rect::new(col as i32, start, 1, line_height);
+    let camera = args.camera.as_deref().map(CameraPath::load).transpose()?;
+    let frames = match (args.frames, playback) {
+        (Some(frames), _) => frames,
+        (None, Some(demo)) => demo.frames.len(),
+        (None, None) => return Err(anyhow!("--sequence needs --frames, or a demo to take its length from"))
+    };
 
-                canvas.copy(&texture.inner, src, dst).unwrap();
-                canvas.set_draw_color(shade);
-                canvas.draw_line((col as i32, start), (col as i32, start + line_height as i32 - 1)).unwrap();
-            }
+    for i in 0..frames {
+        if let Some(camera) = &camera {
+            camera.place(player, i as f32 / (frames - 1).max(1) as f32);
+        } else if let Some(demo) = playback {
+            let input = demo.frames.get(i).copied().unwrap_or_default();
+            simulation::step(map, player, &input, 1.0 / demo.ticks_per_second);
+        } else {
+            // Nothing moves the camera, but doors still animate
+            simulation::step(map, player, &TickInput::default(), 1.0 / TICKS_PER_SECOND);
         }
-        
-        canvas.present();
 
-        // Wait until the next frame
-        unsafe {
-            let time = time_left(next_time);
-            SDL_Delay(time);
-            next_time += TICK_INTERVAL;
-        }
+        renderer.render(map, player);
+        capture::save_png(&renderer, capture::sequence_frame_path(dir, i))?;
     }
 
+    println!("Saved {} frames to {}", frames, dir);
     Ok(())
 }
-
-unsafe fn time_left(next_time: u32) -> u32 {
-    let now = SDL_GetTicks();
-    if next_time <= now {
-        0
-    } else {
-        next_time - now
-    }
-}
\ No newline at end of file
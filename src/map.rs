@@ -1,62 +1,109 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
 use nalgebra::Vector2;
 use sdl2::pixels::Color;
 
+use crate::broadphase::SegmentGrid;
+use crate::collision::{Segment, Segments};
+use crate::door::Door;
+use crate::legend::Legend;
+use crate::sprite::Sprite;
 use crate::texture;
+use crate::tile::{Face, TileDef};
 
 
 // must impl Default + Copy + Clone
-type CellType = u8;
+pub type CellType = u16;
 
-pub struct Map<'a> {
+pub struct Map {
     pub width: u32,
     pub height: u32,
     pub cell_size: f32,
     data: Vec<CellType>,
-    pub textures: Vec<texture::Texture<'a>>,
+    floors: Vec<CellType>,
+    ceilings: Vec<CellType>,
+    pub textures: Vec<texture::Texture>,
     pub ceiling: Color,
     pub floor: Color,
-    pub segments: Vec<(Vector2<f32>, Vector2<f32>)>
+    /// Static wall segments, see `regenerate_segments`
+    pub segments: Vec<(Vector2<f32>, Vector2<f32>)>,
+    /// `segments` sorted into cells for collision queries
+    pub segment_grid: SegmentGrid,
+    pub sprites: Vec<Sprite>,
+    pub doors: HashMap<(u32, u32), Door>,
+    /// Definitions of each nonzero tile, `tiles[n - 1]` describes tile `n`.
+    /// Tiles without a definition use `textures[n - 1]` on every face.
+    pub tiles: Vec<TileDef>
 }
 
 pub struct RaycastResult {
     pub cell: (u32, u32),
     pub index: usize,
     pub pos: (f32, f32),
-    pub u: f32
+    pub u: f32,
+    /// Face of the cell that was hit
+    pub face: Face,
+    /// Outward normal of the face that was hit
+    pub normal: (f32, f32)
+}
+
+impl RaycastResult {
+    fn new(cell: (u32, u32), index: usize, pos: (f32, f32), u: f32, face: Face) -> Self {
+        Self { cell, index, pos, u, face, normal: face.normal() }
+    }
+}
+
+/// Index of the cell containing world coordinate `pos` along one axis. Cell `n` spans from
+/// `n * cell_size` up to `(n + 1) * cell_size`; this and `cell_start` are the only places that
+/// convert between the two, so rendering, collision and gameplay all agree on where cells are.
+pub fn cell_index(pos: f32, cell_size: f32) -> i32 {
+    (pos / cell_size).floor() as i32
+}
+
+/// World coordinate where cell `index` starts along one axis, see `cell_index`
+pub fn cell_start(index: i32, cell_size: f32) -> f32 {
+    index as f32 * cell_size
 }
 
 fn raycast_helpers(cell_size: f32, pos: f32, dir: f32) -> (i32, i32, f32, f32) {
-    let tile = (pos / cell_size).floor() + 1.0;
+    let tile = cell_index(pos, cell_size);
 
     let d_tile;
     let dt;
 
     if dir > 0.0 {
         d_tile = 1;
-        dt = (tile * cell_size - pos) / dir;
+        dt = (cell_start(tile + 1, cell_size) - pos) / dir;
     } else if dir == 0.0 {
-        return (tile as i32, 0, std::f32::MAX, 0.0)
+        return (tile, 0, f32::MAX, 0.0)
     } else {
         d_tile = -1;
-        dt = ((tile - 1.0) * cell_size - pos) / dir;
+        dt = (cell_start(tile, cell_size) - pos) / dir;
     }
 
-    (tile as i32, d_tile, dt, d_tile as f32 * cell_size / dir)
+    (tile, d_tile, dt, d_tile as f32 * cell_size / dir)
 }
 
-impl<'a> Map<'a> {
+impl Map {
     pub fn new(width: u32, height: u32, cell_size: f32) -> Self {
         Self {
             width, height, cell_size,
             data: vec![CellType::default(); (width * height) as usize],
+            floors: vec![CellType::default(); (width * height) as usize],
+            ceilings: vec![CellType::default(); (width * height) as usize],
             textures: Vec::new(),
             ceiling: Color::BLACK,
             floor: Color::BLACK,
-            segments: Vec::new()
+            segments: Vec::new(),
+            segment_grid: SegmentGrid::new(width, height, cell_size),
+            sprites: Vec::new(),
+            doors: HashMap::new(),
+            tiles: Vec::new()
         }
     }
 
-    pub fn add_texture(&mut self, texture: texture::Texture<'a>) -> usize {
+    pub fn add_texture(&mut self, texture: texture::Texture) -> usize {
         self.textures.push(texture);
         self.textures.len() - 1
     }
@@ -69,35 +116,256 @@ impl<'a> Map<'a> {
         self.data[(y * self.width + x) as usize]
     }
 
-    pub fn from_string(string: &str, width: u32, height: u32, cell_size: f32) -> Self {
+    /// Returns the cell containing world position `x`, `y`
+    pub fn cell_at(&self, x: f32, y: f32) -> Option<(u32, u32)> {
+        let cell_x = cell_index(x, self.cell_size);
+        let cell_y = cell_index(y, self.cell_size);
+
+        if cell_x >= 0 && cell_y >= 0 && cell_x < self.width as i32 && cell_y < self.height as i32 {
+            Some((cell_x as u32, cell_y as u32))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the definition of nonzero tile `tile`
+    pub fn tile(&self, tile: CellType) -> TileDef {
+        self.tiles.get(tile as usize - 1)
+            .copied()
+            .unwrap_or(TileDef::uniform(tile as usize - 1))
+    }
+
+    /// Returns true if rays continue past walls of type `tile`
+    pub fn is_transparent(&self, tile: CellType) -> bool {
+        tile != 0 && self.tile(tile).transparent
+    }
+
+    /// Returns true if cells of type `tile` block movement
+    pub fn is_solid(&self, tile: CellType) -> bool {
+        tile != 0 && self.tile(tile).solid
+    }
+
+    /// Returns the world position of the top left corner of a cell
+    pub fn cell_origin(&self, x: u32, y: u32) -> (f32, f32) {
+        (cell_start(x as i32, self.cell_size), cell_start(y as i32, self.cell_size))
+    }
+
+    /// Floor texture of a cell, `0` for the flat `floor` color
+    pub fn set_floor(&mut self, x: u32, y: u32, to: CellType) {
+        self.floors[(y * self.width + x) as usize] = to;
+    }
+
+    pub fn get_floor(&self, x: u32, y: u32) -> CellType {
+        self.floors[(y * self.width + x) as usize]
+    }
+
+    /// Ceiling texture of a cell, `0` for the flat `ceiling` color
+    pub fn set_ceiling(&mut self, x: u32, y: u32, to: CellType) {
+        self.ceilings[(y * self.width + x) as usize] = to;
+    }
+
+    pub fn get_ceiling(&self, x: u32, y: u32) -> CellType {
+        self.ceilings[(y * self.width + x) as usize]
+    }
+
+    /// Returns true if any cell has a textured floor
+    pub fn has_floor_textures(&self) -> bool {
+        self.floors.iter().any(|&id| id != 0)
+    }
+
+    /// Returns true if any cell has a textured ceiling
+    pub fn has_ceiling_textures(&self) -> bool {
+        self.ceilings.iter().any(|&id| id != 0)
+    }
+
+    /// Parses a map from a grid of numbers, one row per line. Dimensions are inferred from the
+    /// grid, and every row must have the same width.
+    pub fn parse(string: &str, cell_size: f32) -> anyhow::Result<Self> {
+        Self::parse_with_legend(string, cell_size, &Legend::Numeric)
+    }
+
+    /// Parses a map from a grid of symbols described by `legend`
+    pub fn parse_with_legend(string: &str, cell_size: f32, legend: &Legend) -> anyhow::Result<Self> {
+        let (width, height, data) = legend.parse_grid(string)?;
         let mut map = Self::new(width, height, cell_size);
+        map.data = data;
+
+        map.regenerate_segments();
 
-        let mut chars = string.chars();
-        for y in 0..height {
-            for x in 0..width {
-                let id = chars.next().unwrap().to_digit(10).unwrap();
-                map.set(x, y, id as u8);
+        Ok(map)
+    }
+
+    /// Parses a grid of floor textures with the same dimensions as the map
+    pub fn parse_floors(&mut self, string: &str) -> anyhow::Result<()> {
+        self.floors = self.parse_layer(string)?;
+        Ok(())
+    }
+
+    /// Parses a grid of ceiling textures with the same dimensions as the map
+    pub fn parse_ceilings(&mut self, string: &str) -> anyhow::Result<()> {
+        self.ceilings = self.parse_layer(string)?;
+        Ok(())
+    }
+
+    fn parse_layer(&self, string: &str) -> anyhow::Result<Vec<CellType>> {
+        let (width, height, data) = Legend::Numeric.parse_grid(string)?;
+        if (width, height) != (self.width, self.height) {
+            return Err(anyhow!("expected a {}x{} grid, found {}x{}", self.width, self.height, width, height));
+        }
+
+        Ok(data)
+    }
+
+    /// Turns every cell of type `tile` into a door. Doors run between the solid cells on either
+    /// side of them, along x if the cells to the left and right are solid and along y otherwise.
+    pub fn add_doors(&mut self, tile: CellType) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.get(x, y) != tile {
+                    continue;
+                }
+
+                let solid = |x: i32, y: i32| x >= 0 && y >= 0 && x < self.width as i32 && y < self.height as i32 && self.get(x as u32, y as u32) != 0;
+                let horizontal = solid(x as i32 - 1, y as i32) && solid(x as i32 + 1, y as i32);
+                self.doors.insert((x, y), Door::new(horizontal));
             }
         }
 
-        map.regenerate_segments();
+        self.regenerate_segments();
+    }
+
+    /// Activates the door the ray from `x`, `y` along `dir_x`, `dir_y` hits within `reach`.
+    /// Returns true if there was one.
+    pub fn use_door(&mut self, x: f32, y: f32, dir_x: f32, dir_y: f32, reach: f32) -> bool {
+        let Some(result) = self.cast_ray(x, y, dir_x, dir_y) else {
+            return false;
+        };
 
-        map
+        let distance = ((result.pos.0 - x).powf(2.0) + (result.pos.1 - y).powf(2.0)).sqrt();
+        match self.doors.get_mut(&result.cell) {
+            Some(door) if distance <= reach => {
+                door.activate();
+                true
+            }
+            _ => false
+        }
     }
 
+    /// Advances every door by `dt` seconds. Doors stay open while a circle at `pos` with radius `r` is inside them.
+    pub fn update_doors(&mut self, dt: f32, pos: (f32, f32), r: f32) {
+        let cells: Vec<(u32, u32)> = self.doors.keys().copied().collect();
+        for cell in cells {
+            let origin = self.cell_origin(cell.0, cell.1);
+            let blocked = pos.0 + r > origin.0 && pos.0 - r < origin.0 + self.cell_size
+                && pos.1 + r > origin.1 && pos.1 - r < origin.1 + self.cell_size;
+
+            if let Some(door) = self.doors.get_mut(&cell) {
+                door.update(dt, blocked);
+            }
+        }
+    }
+
+    /// Returns the segment covering the closed part of a door, or `None` if it is fully open
+    fn door_segment(&self, cell: (u32, u32), door: &Door) -> Option<(Vector2<f32>, Vector2<f32>)> {
+        if door.is_open() {
+            return None;
+        }
+
+        let (x, y) = self.cell_origin(cell.0, cell.1);
+        let half = self.cell_size / 2.0;
+        let start = door.open * self.cell_size;
+
+        Some(if door.horizontal {
+            (Vector2::new(x + start, y + half), Vector2::new(x + self.cell_size, y + half))
+        } else {
+            (Vector2::new(x + half, y + start), Vector2::new(x + half, y + self.cell_size))
+        })
+    }
+
+    /// Returns the static wall segments along with the segments of any doors that are not fully open
+    pub fn collision_segments(&self) -> Vec<(Vector2<f32>, Vector2<f32>)> {
+        let mut segments = self.segments.clone();
+        segments.extend(self.doors.iter().filter_map(|(&cell, door)| self.door_segment(cell, door)));
+        segments
+    }
+
+    /// Intersects a ray with the door in `cell`, which the ray has just entered
+    fn cast_door(&self, cell: (u32, u32), door: &Door, x: f32, y: f32, dir_x: f32, dir_y: f32) -> Option<RaycastResult> {
+        let (origin_x, origin_y) = self.cell_origin(cell.0, cell.1);
+        let half = self.cell_size / 2.0;
+
+        // Distance along the ray to the door plane, where along the door it is crossed, and which side was hit
+        let (t, along, face) = if door.horizontal {
+            if dir_y == 0.0 {
+                return None;
+            }
+            let t = (origin_y + half - y) / dir_y;
+            (t, x + dir_x * t - origin_x, if dir_y > 0.0 { Face::North } else { Face::South })
+        } else {
+            if dir_x == 0.0 {
+                return None;
+            }
+            let t = (origin_x + half - x) / dir_x;
+            (t, y + dir_y * t - origin_y, if dir_x > 0.0 { Face::West } else { Face::East })
+        };
+
+        let u = along / self.cell_size;
+        if t < 0.0 || u < door.open || u >= 1.0 {
+            return None;
+        }
+
+        Some(RaycastResult::new(
+            cell,
+            (cell.1 * self.width + cell.0) as usize,
+            (x + dir_x * t, y + dir_y * t),
+            u - door.open,
+            face
+        ))
+    }
+
+    /// Returns the first wall hit by a ray, including transparent ones
     pub fn cast_ray(&self, x: f32, y: f32, dir_x: f32, dir_y: f32) -> Option<RaycastResult> {
+        let mut hit = None;
+        self.walk_ray(x, y, dir_x, dir_y, |result| {
+            hit = Some(result);
+            false
+        });
+        hit
+    }
+
+    /// Returns every wall hit by a ray, nearest first, continuing past transparent tiles
+    /// and stopping at the first opaque one
+    pub fn cast_ray_through(&self, x: f32, y: f32, dir_x: f32, dir_y: f32) -> Vec<RaycastResult> {
+        let mut hits = Vec::new();
+        self.walk_ray(x, y, dir_x, dir_y, |result| {
+            let transparent = self.is_transparent(self.get(result.cell.0, result.cell.1));
+            hits.push(result);
+            transparent
+        });
+        hits
+    }
+
+    /// Walks a ray through the grid, calling `hit` for each wall it hits in order until `hit` returns false
+    fn walk_ray<F: FnMut(RaycastResult) -> bool>(&self, x: f32, y: f32, dir_x: f32, dir_y: f32, mut hit: F) {
         let (mut tile_x, dtile_x, mut dt_x, ddt_x) = raycast_helpers(self.cell_size, x, dir_x);
         let (mut tile_y, dtile_y, mut dt_y, ddt_y) = raycast_helpers(self.cell_size, y, dir_y);
 
         let mut t = 0.0;
 
-        let mut cur_x: f32 = 0.0;
-        let mut cur_y: f32 = 0.0;
+        // A ray starting inside a wall hits it where it starts
+        let mut cur_x = x;
+        let mut cur_y = y;
         let mut was_y = dt_y < dt_x;
 
         if dir_x.powf(2.0) + dir_y.powf(2.0) > 0.0 {
             while tile_x >= 0 && tile_x < self.width as i32 && tile_y >= 0 && tile_y < self.height as i32 {
-                if self.get(tile_x as u32, tile_y as u32) != 0 {
+                if let Some(door) = self.doors.get(&(tile_x as u32, tile_y as u32)) {
+                    if let Some(result) = self.cast_door((tile_x as u32, tile_y as u32), door, x, y, dir_x, dir_y) {
+                        if !hit(result) {
+                            return;
+                        }
+                    }
+                } else if self.get(tile_x as u32, tile_y as u32) != 0 {
 
                     let u = if was_y {
                         cur_x.abs() % self.cell_size
@@ -105,12 +373,24 @@ impl<'a> Map<'a> {
                         cur_y.abs() % self.cell_size
                     };
 
-                    return Some(RaycastResult {
-                        cell: (tile_x as u32, tile_y as u32),
-                        index: (tile_y * self.width as i32 + tile_x) as usize,
-                        pos: (cur_x, cur_y),
-                        u: u / self.cell_size
-                    })
+                    let face = match (was_y, dir_x > 0.0, dir_y > 0.0) {
+                        (true, _, true) => Face::North,
+                        (true, _, false) => Face::South,
+                        (false, true, _) => Face::West,
+                        (false, false, _) => Face::East
+                    };
+
+                    let result = RaycastResult::new(
+                        (tile_x as u32, tile_y as u32),
+                        (tile_y * self.width as i32 + tile_x) as usize,
+                        (cur_x, cur_y),
+                        u / self.cell_size,
+                        face
+                    );
+
+                    if !hit(result) {
+                        return;
+                    }
                 }
 
                 if dt_x < dt_y {
@@ -132,43 +412,469 @@ impl<'a> Map<'a> {
                 cur_x = x + dir_x * t;
                 cur_y = y + dir_y * t;
             }
-        } else if self.get(tile_x as u32, tile_y as u32) != 0 {
-            return Some(RaycastResult {
-                cell: (tile_x as u32, tile_y as u32),
-                index: (tile_y * self.width as i32 + tile_x) as usize,
-                pos: (cur_x, cur_y),
-                u: 0.5
-            })
+        } else if self.cell_at(x, y).is_some_and(|(cell_x, cell_y)| self.get(cell_x, cell_y) != 0) {
+            hit(RaycastResult::new(
+                (tile_x as u32, tile_y as u32),
+                (tile_y * self.width as i32 + tile_x) as usize,
+                (cur_x, cur_y),
+                0.5,
+                Face::North
+            ));
         }
-
-        None
     }
 
     pub fn shade(&self, distance: f32) -> f32 {
         ((1.0 / distance) * 2.0).min(1.0)
     }
 
-    pub fn shade_from_height(&self, height: u32, screen_height: u32) -> f32 {
-        ((2.0 * height as f32) / (screen_height as f32)).min(1.0)
+    /// Returns true if the cell at `x`, `y` is walled in by static segments. Cells outside the
+    /// map count as blocking, since nothing can get to them
+    fn blocks(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return true;
+        }
+
+        let (x, y) = (x as u32, y as u32);
+        self.is_solid(self.get(x, y)) && !self.doors.contains_key(&(x, y))
     }
 
-    fn add_segment_square(&mut self, cell_x: u32, cell_y: u32) {
-        let x = (cell_x as f32 - 1.0) * self.cell_size;
-        let y = (cell_y as f32 - 1.0) * self.cell_size;
-        self.segments.push((Vector2::new(x, y), Vector2::new(x, y + self.cell_size)));
-        self.segments.push((Vector2::new(x, y + self.cell_size), Vector2::new(x + self.cell_size, y + self.cell_size)));
-        self.segments.push((Vector2::new(x + self.cell_size, y + self.cell_size), Vector2::new(x + self.cell_size, y)));
-        self.segments.push((Vector2::new(x + self.cell_size, y), Vector2::new(x, y)));
+    /// Adds a segment for every run of blocking cells whose `face` borders a cell that doesn't block.
+    /// Segments run so that their left side is the outside of the wall
+    fn add_face_segments(&mut self, face: Face) {
+        let (normal_x, normal_y) = face.normal();
+        let (dx, dy) = (normal_x as i32, normal_y as i32);
+        let horizontal = dy != 0;
+        let (lines, length) = if horizontal { (self.height, self.width) } else { (self.width, self.height) };
+
+        for line in 0..lines {
+            let mut run_start = None;
+            for along in 0..=length {
+                let (x, y) = if horizontal { (along as i32, line as i32) } else { (line as i32, along as i32) };
+                let exposed = along < length && self.blocks(x, y) && !self.blocks(x + dx, y + dy);
+
+                match (exposed, run_start) {
+                    (true, None) => run_start = Some(along),
+                    (false, Some(start)) => {
+                        let (first, end) = if horizontal { ((start, line), (along, line)) } else { ((line, start), (line, along)) };
+                        let (ax, ay) = self.cell_origin(first.0, first.1);
+                        let (bx, by) = self.cell_origin(end.0, end.1);
+
+                        // Move the South and East faces over to the far side of the cells
+                        let (ox, oy) = (dx.max(0) as f32 * self.cell_size, dy.max(0) as f32 * self.cell_size);
+                        let (a, b) = (Vector2::new(ax + ox, ay + oy), Vector2::new(bx + ox, by + oy));
+
+                        self.segments.push(match face {
+                            Face::South | Face::West => (a, b),
+                            Face::North | Face::East => (b, a)
+                        });
+                        run_start = None;
+                    }
+                    _ => ()
+                }
+            }
+        }
     }
 
-    // TODO: joining, removing redundant segments
+    /// Rebuilds the static collision segments: the outline of the solid cells, with edges shared
+    /// by two solid cells left out and edges in a straight line joined together
     pub fn regenerate_segments(&mut self) {
-        for y in 0..self.height {
-            for x in 0..self.width {
-                if self.get(x, y) > 0 {
-                    self.add_segment_square(x, y);
+        self.segments.clear();
+
+        for face in [Face::North, Face::South, Face::East, Face::West] {
+            self.add_face_segments(face);
+        }
+
+        self.segment_grid.rebuild(&self.segments);
+    }
+
+    /// Returns everything that collides: the static segments through the grid, and any doors
+    /// that are not fully open
+    pub fn collision(&self) -> MapSegments<'_> {
+        MapSegments {
+            grid: &self.segment_grid,
+            doors: self.doors.iter().filter_map(|(&cell, door)| self.door_segment(cell, door)).collect()
+        }
+    }
+}
+
+#[cfg(test)]
+impl Map {
+    /// One of the empty cells, chosen by `index`, for property tests that start somewhere open
+    pub(crate) fn empty_cell(&self, index: proptest::sample::Index) -> (u32, u32) {
+        let empty: Vec<_> = (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| self.get(x, y) == 0)
+            .collect();
+        empty[index.index(empty.len())]
+    }
+}
+
+/// The map's collision segments at one point in time, see `Map::collision`
+pub struct MapSegments<'a> {
+    grid: &'a SegmentGrid,
+    doors: Vec<Segment>
+}
+
+impl Segments for MapSegments<'_> {
+    fn near<F: FnMut(&Segment)>(&self, pos: Vector2<f32>, r: f32, mut f: F) {
+        self.grid.near(pos, r, &mut f);
+        self.doors.iter().for_each(f);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+    use proptest::prelude::*;
+
+    const ROOM: &str = "\
+1111111
+1000001
+1020101
+1000001
+1011001
+1000001
+1111111";
+
+    fn room(cell_size: f32) -> Map {
+        Map::parse(ROOM, cell_size).unwrap()
+    }
+
+    /// World position of the center of a cell
+    fn center(map: &Map, x: u32, y: u32) -> (f32, f32) {
+        let (ox, oy) = map.cell_origin(x, y);
+        (ox + map.cell_size / 2.0, oy + map.cell_size / 2.0)
+    }
+
+    #[test]
+    fn raycast_helpers_step_towards_the_next_boundary() {
+        // Moving forwards the first boundary is the far side of the tile
+        let (tile, d_tile, dt, ddt) = raycast_helpers(2.0, 3.0, 0.5);
+        assert_eq!((tile, d_tile), (1, 1));
+        assert_eq!(dt, 2.0);
+        assert_eq!(ddt, 4.0);
+
+        // Moving backwards it is the near side, and steps still take positive time
+        let (tile, d_tile, dt, ddt) = raycast_helpers(2.0, 3.0, -0.5);
+        assert_eq!((tile, d_tile), (1, -1));
+        assert_eq!(dt, 2.0);
+        assert_eq!(ddt, 4.0);
+    }
+
+    #[test]
+    fn raycast_helpers_never_step_without_direction() {
+        let (tile, d_tile, dt, ddt) = raycast_helpers(1.0, 2.5, 0.0);
+        assert_eq!((tile, d_tile), (2, 0));
+        assert_eq!(dt, f32::MAX);
+        assert_eq!(ddt, 0.0);
+
+        let (_, d_tile, dt, _) = raycast_helpers(1.0, 2.5, -0.0);
+        assert_eq!(d_tile, 0);
+        assert_eq!(dt, f32::MAX);
+    }
+
+    #[test]
+    fn axis_aligned_rays_hit_the_facing_side() {
+        let map = room(1.0);
+        let (x, y) = center(&map, 3, 3);
+
+        let cases = [
+            ((1.0, 0.0), Face::West, (6, 3)),
+            ((-1.0, 0.0), Face::East, (0, 3)),
+            ((0.0, 1.0), Face::North, (3, 4)),
+            ((0.0, -1.0), Face::South, (3, 0))
+        ];
+
+        for (dir, face, cell) in cases {
+            let hit = map.cast_ray(x, y, dir.0, dir.1).unwrap();
+            assert_eq!(hit.face, face, "ray {:?}", dir);
+            assert_eq!(hit.cell, cell, "ray {:?}", dir);
+            assert_eq!(hit.normal, face.normal());
+            assert_eq!(hit.index, (cell.1 * map.width + cell.0) as usize);
+        }
+    }
+
+    #[test]
+    fn hit_position_and_texture_coordinate() {
+        let map = room(2.0);
+        let (x, y) = center(&map, 1, 1);
+        let hit = map.cast_ray(x, y, 1.0, 0.0).unwrap();
+
+        // The ray runs through the middle of the cell's height, halfway along the face
+        assert_eq!(hit.cell, (6, 1));
+        assert_eq!(hit.pos, (map.cell_origin(6, 1).0, y));
+        assert!((hit.u - 0.5).abs() < 1e-5, "u = {}", hit.u);
+    }
+
+    #[test]
+    fn rays_leaving_the_grid_hit_nothing() {
+        let map = Map::parse("000\n000\n000", 1.0).unwrap();
+        let (x, y) = center(&map, 1, 1);
+
+        for dir in [(1.0, 0.0), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0), (0.6, -0.8), (-1e-6, 1.0)] {
+            assert!(map.cast_ray(x, y, dir.0, dir.1).is_none(), "ray {:?}", dir);
+        }
+
+        // Starting outside the grid
+        assert!(map.cast_ray(-10.0, -10.0, 1.0, 1.0).is_none());
+        assert!(map.cast_ray(-10.0, -10.0, 0.0, 0.0).is_none());
+    }
+
+    #[test]
+    fn rays_starting_in_a_wall_hit_it_where_they_start() {
+        let map = room(1.0);
+        let (x, y) = center(&map, 2, 2);
+
+        let hit = map.cast_ray(x, y, 1.0, 0.3).unwrap();
+        assert_eq!(hit.cell, (2, 2));
+        assert_eq!(hit.pos, (x, y));
+
+        let hit = map.cast_ray(x, y, 0.0, 0.0).unwrap();
+        assert_eq!(hit.cell, (2, 2));
+        assert_eq!(hit.pos, (x, y));
+    }
+
+    #[test]
+    fn rays_without_direction_in_empty_space_hit_nothing() {
+        let map = room(1.0);
+        let (x, y) = center(&map, 3, 3);
+        assert!(map.cast_ray(x, y, 0.0, 0.0).is_none());
+    }
+
+    #[test]
+    fn cast_ray_through_stops_at_the_first_opaque_wall() {
+        let mut map = Map::parse("11111\n10001\n10231\n11111", 1.0).unwrap();
+        map.tiles = vec![TileDef::uniform(0), TileDef { transparent: true, ..TileDef::uniform(1) }, TileDef::uniform(2)];
+
+        let (x, y) = center(&map, 1, 2);
+        let cells: Vec<_> = map.cast_ray_through(x, y, 1.0, 0.0).iter().map(|hit| hit.cell).collect();
+        assert_eq!(cells, [(2, 2), (3, 2)]);
+
+        // cast_ray only returns the transparent wall in front
+        assert_eq!(map.cast_ray(x, y, 1.0, 0.0).unwrap().cell, (2, 2));
+
+        let (x, y) = center(&map, 1, 1);
+        let cells: Vec<_> = map.cast_ray_through(x, y, 1.0, 0.0).iter().map(|hit| hit.cell).collect();
+        assert_eq!(cells, [(4, 1)]);
+    }
+
+    /// Four segments around every solid cell, the way segments used to be generated
+    fn square_segments(map: &Map) -> Vec<(Vector2<f32>, Vector2<f32>)> {
+        let mut segments = Vec::new();
+        for y in 0..map.height {
+            for x in 0..map.width {
+                if map.is_solid(map.get(x, y)) && !map.doors.contains_key(&(x, y)) {
+                    let (x, y) = map.cell_origin(x, y);
+                    let cs = map.cell_size;
+                    segments.push((Vector2::new(x, y), Vector2::new(x, y + cs)));
+                    segments.push((Vector2::new(x, y + cs), Vector2::new(x + cs, y + cs)));
+                    segments.push((Vector2::new(x + cs, y + cs), Vector2::new(x + cs, y)));
+                    segments.push((Vector2::new(x + cs, y), Vector2::new(x, y)));
+                }
+            }
+        }
+        segments
+    }
+
+    #[test]
+    fn segments_outline_blocks_of_walls() {
+        let map = Map::parse("00000\n01110\n01110\n00000", 2.0).unwrap();
+        assert_eq!(map.segments.len(), 4);
+
+        let (x, y) = map.cell_origin(1, 1);
+        let mut segments = map.segments.clone();
+        segments.sort_by(|a, b| (a.0.x, a.0.y, a.1.x).partial_cmp(&(b.0.x, b.0.y, b.1.x)).unwrap());
+        assert_eq!(segments, [
+            (Vector2::new(x, y), Vector2::new(x, y + 4.0)),
+            (Vector2::new(x, y + 4.0), Vector2::new(x + 6.0, y + 4.0)),
+            (Vector2::new(x + 6.0, y), Vector2::new(x, y)),
+            (Vector2::new(x + 6.0, y + 4.0), Vector2::new(x + 6.0, y))
+        ]);
+    }
+
+    #[test]
+    fn segments_leave_out_the_outside_and_shared_edges() {
+        // Only the inside of the outer wall and the outline of the pillar remain
+        let map = room(1.0);
+        let inner = 4;
+        let pillars = 4 + 4;
+        let bar = 4;
+        assert_eq!(map.segments.len(), inner + pillars + bar);
+
+        for &(a, b) in map.segments.iter() {
+            // The outside of every segment is empty, the inside solid
+            let middle = (a + b) / 2.0;
+            let normal = Vector2::new(-(b - a).y, (b - a).x).normalize() * 0.01;
+            let outside = map.cell_at(middle.x + normal.x, middle.y + normal.y).unwrap();
+            let inside = map.cell_at(middle.x - normal.x, middle.y - normal.y).unwrap();
+            assert_eq!(map.get(outside.0, outside.1), 0, "segment {:?} {:?}", a, b);
+            assert_ne!(map.get(inside.0, inside.1), 0, "segment {:?} {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn segments_face_into_door_cells() {
+        let mut map = Map::parse("111\n121\n111", 1.0).unwrap();
+        map.add_doors(2);
+
+        // The door cell doesn't block, so every wall around it faces into it, and the door adds its own
+        assert_eq!(map.segments.len(), 4);
+        assert_eq!(map.collision_segments().len(), 5);
+    }
+
+    #[test]
+    fn cells_map_to_world_squares() {
+        let map = room(2.0);
+        assert_eq!(map.cell_origin(0, 0), (0.0, 0.0));
+        assert_eq!(map.cell_origin(3, 1), (6.0, 2.0));
+
+        for y in 0..map.height {
+            for x in 0..map.width {
+                let (ox, oy) = map.cell_origin(x, y);
+                assert_eq!(map.cell_at(ox, oy), Some((x, y)));
+                assert_eq!(map.cell_at(ox + 1.99, oy + 1.99), Some((x, y)));
+            }
+        }
+
+        assert_eq!(map.cell_at(-0.01, 1.0), None);
+        assert_eq!(map.cell_at(1.0, 14.0), None);
+    }
+
+    /// Walks a circle from `start` along `dir` in steps shorter than its radius until it stops
+    fn walk_until_stopped(map: &Map, start: (f32, f32), dir: (f32, f32), r: f32) -> Vector2<f32> {
+        let mut pos = Vector2::new(start.0, start.1);
+        for _ in 0..1000 {
+            let next = crate::collision::slide_move(pos, r, Vector2::new(dir.0, dir.1) * r * 0.5, &map.collision_segments());
+            if (next - pos).magnitude() < 1e-6 {
+                break;
+            }
+            pos = next;
+        }
+        pos
+    }
+
+    #[test]
+    fn walking_at_a_visible_wall_stops_a_radius_in_front_of_it() {
+        let mut door = Map::parse("11111\n10001\n11211\n10001\n11111", 1.0).unwrap();
+        door.add_doors(2);
+
+        for map in [room(1.0), door] {
+            for y in 0..map.height {
+                for x in 0..map.width {
+                    if map.get(x, y) != 0 {
+                        continue;
+                    }
+
+                    let start = center(&map, x, y);
+                    for dir in [(1.0, 0.0), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0)] {
+                        let hit = map.cast_ray(start.0, start.1, dir.0, dir.1).unwrap();
+                        let stop = walk_until_stopped(&map, start, dir, 0.25);
+
+                        let expected = Vector2::new(hit.pos.0 - dir.0 * 0.25, hit.pos.1 - dir.1 * 0.25);
+                        assert!((stop - expected).magnitude() < 1e-3,
+                            "walking {:?} from {:?} stopped at {:?}, but the wall is drawn at {:?}", dir, start, stop, hit.pos);
+                    }
                 }
             }
         }
     }
-}
\ No newline at end of file
+
+    /// A cell size, a start position somewhere in an empty cell of the room and a ray direction
+    fn ray() -> impl Strategy<Value = (f32, f32, f32, f32, f32)> {
+        (prop_oneof![Just(1.0f32), Just(64.0), 0.1f32..10.0], any::<prop::sample::Index>(), 0.0f32..1.0, 0.0f32..1.0, -PI..PI)
+            .prop_map(|(cell_size, cell, fx, fy, angle)| {
+                let map = room(cell_size);
+                let (cell_x, cell_y) = map.empty_cell(cell);
+                let (ox, oy) = map.cell_origin(cell_x, cell_y);
+                let (x, y) = (ox + fx * cell_size * 0.999, oy + fy * cell_size * 0.999);
+                (cell_size, x, y, angle.cos(), angle.sin())
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn hits_lie_on_the_facing_side_of_a_solid_cell((cell_size, x, y, dir_x, dir_y) in ray()) {
+            let map = room(cell_size);
+
+            // The room is closed, so every ray from inside hits something
+            let hit = map.cast_ray(x, y, dir_x, dir_y).unwrap();
+            prop_assert!(map.get(hit.cell.0, hit.cell.1) != 0);
+
+            let epsilon = 1e-3 * cell_size;
+            let (ox, oy) = map.cell_origin(hit.cell.0, hit.cell.1);
+            let (px, py) = hit.pos;
+            prop_assert!(px >= ox - epsilon && px <= ox + cell_size + epsilon, "{:?} outside cell {:?}", hit.pos, hit.cell);
+            prop_assert!(py >= oy - epsilon && py <= oy + cell_size + epsilon, "{:?} outside cell {:?}", hit.pos, hit.cell);
+
+            let on_side = match hit.face {
+                Face::North => (py - oy).abs() < epsilon,
+                Face::South => (py - oy - cell_size).abs() < epsilon,
+                Face::West => (px - ox).abs() < epsilon,
+                Face::East => (px - ox - cell_size).abs() < epsilon
+            };
+            prop_assert!(on_side, "{:?} is not on the {:?} side of {:?}", hit.pos, hit.face, hit.cell);
+
+            // The face points back towards the ray
+            prop_assert!(hit.normal.0 * dir_x + hit.normal.1 * dir_y <= 0.0);
+            prop_assert!((0.0..=1.0).contains(&hit.u), "u = {}", hit.u);
+        }
+
+        #[test]
+        fn merged_segments_collide_like_squares(
+            (cell_size, x, y, dir_x, dir_y) in ray(),
+            speed in 0.0f32..1.0,
+            radius in 0.05f32..0.45
+        ) {
+            let map = room(cell_size);
+            let squares = square_segments(&map);
+            let start = Vector2::new(x, y);
+
+            // Start clear of the walls, and move less than the radius so the move can't pass
+            // through the edge of a wall
+            let clearance = squares.iter()
+                .map(|&(a, b)| (start - crate::collision::closest_point_on_segment(start, a, b)).magnitude())
+                .fold(f32::INFINITY, f32::min);
+            let r = (radius * cell_size).min(clearance);
+            prop_assume!(r > 1e-3 * cell_size);
+            let speed = speed * r;
+
+            let vel = Vector2::new(dir_x, dir_y) * speed;
+            let merged = crate::collision::slide_move(start, r, vel, &map.segments);
+            let expected = crate::collision::slide_move(start, r, vel, &squares);
+            prop_assert!((merged - expected).magnitude() < 1e-3 * cell_size, "{:?} != {:?}", merged, expected);
+        }
+
+        #[test]
+        fn ray_hits_lie_on_collision_segments((cell_size, x, y, dir_x, dir_y) in ray()) {
+            let map = room(cell_size);
+            let hit = map.cast_ray(x, y, dir_x, dir_y).unwrap();
+            let pos = Vector2::new(hit.pos.0, hit.pos.1);
+
+            let distance = map.collision_segments().iter()
+                .map(|&(a, b)| (pos - crate::collision::closest_point_on_segment(pos, a, b)).magnitude())
+                .fold(f32::INFINITY, f32::min);
+            prop_assert!(distance < 1e-3 * cell_size, "{:?} is {} away from the nearest segment", hit.pos, distance);
+        }
+
+        #[test]
+        fn rays_pass_only_through_empty_cells((cell_size, x, y, dir_x, dir_y) in ray()) {
+            let map = room(cell_size);
+            prop_assert_eq!(map.cell_at(x, y).map(|(cx, cy)| map.get(cx, cy)), Some(0));
+
+            let hit = map.cast_ray(x, y, dir_x, dir_y).unwrap();
+            let distance = ((hit.pos.0 - x).powi(2) + (hit.pos.1 - y).powi(2)).sqrt();
+
+            let steps = 100;
+            for i in 0..steps {
+                let t = (distance - 1e-2 * cell_size) * i as f32 / steps as f32;
+                if t <= 0.0 {
+                    break;
+                }
+                let cell = map.cell_at(x + dir_x * t, y + dir_y * t).unwrap();
+                prop_assert_eq!(map.get(cell.0, cell.1), 0, "ray passed through {:?} before hitting {:?}", cell, hit.cell);
+            }
+        }
+    }
+}
@@ -2,39 +2,95 @@ use core::f32;
 use core::f32::consts::PI;
 
 use nalgebra::vector;
-use sdl2::keyboard::Keycode;
 
-use crate::collision;
-use crate::input::Input;
+use crate::bindings::Action;
+use crate::collision::{self, Body, Solidity};
+use crate::input::TickInput;
 use crate::map::Map;
+use crate::settings::Settings;
 
+/// Furthest the horizon can be shifted by looking up or down, in screen heights
+pub const MAX_PITCH: f32 = 0.4;
+
+/// Speed below which friction stops the player entirely, in units per second
+const STOP_SPEED: f32 = 0.01;
+
+/// Returns the angle between `from` (at `t` 0.0) and `to` (at `t` 1.0), in radians, turning the
+/// short way round
+pub fn lerp_angle(from: f32, to: f32, t: f32) -> f32 {
+    let turn = (to - from + PI).rem_euclid(2.0 * PI) - PI;
+    from + turn * t
+}
+
+#[derive(Clone)]
 pub struct Player {
     pub position: (f32, f32),
+    /// Units per second
     pub velocity: (f32, f32),
     pub facing: f32,
+    /// How far the view is sheared to look up (positive) or down, in screen heights
+    pub pitch: f32,
     pub forward: (f32, f32),
     pub right: (f32, f32),
+    /// Top walking speed in units per second
     pub speed: f32,
+    /// Top speed while sprinting, relative to `speed`
+    pub sprint_multiplier: f32,
+    /// Fraction of `speed` gained per second while moving
+    pub acceleration: f32,
+    /// Fraction of the current speed lost per second
+    pub friction: f32,
+    /// Radians per second
     pub turn_speed: f32,
+    /// Screen heights per second when looking up and down with a stick
+    pub pitch_speed: f32,
+    /// Radians per pixel of horizontal mouse movement
+    pub yaw_sensitivity: f32,
+    /// Screen heights per pixel of vertical mouse movement
+    pub pitch_sensitivity: f32,
+    pub invert_mouse: bool,
+    pub invert_pitch: bool,
     pub fov: f32,
-    pub radius: f32
+    pub radius: f32,
+    /// How hard the player is to push, and how hard it pushes, see `Body::mass`
+    pub mass: f32
 }
 
 impl Player {
     pub fn new(position: (f32, f32)) -> Self {
+        let look = Settings::default();
         Self {
             position,
             facing: 0.0,
+            pitch: 0.0,
             forward: (1.0, 0.0),
             fov: PI / 4.0,
             right: (0.0, 1.0),
-            speed: 0.1,
-            turn_speed: 0.05,
+            speed: 6.0,
+            sprint_multiplier: 1.6,
+            acceleration: 10.0,
+            friction: 8.0,
+            turn_speed: 3.0,
+            pitch_speed: 1.0,
+            yaw_sensitivity: look.yaw_sensitivity,
+            pitch_sensitivity: look.pitch_sensitivity,
+            invert_mouse: look.invert_mouse,
+            invert_pitch: look.invert_pitch,
             radius: 0.25,
+            mass: 1.0,
             velocity: (0.0, 0.0)
         }
     }
 
+    pub fn body(&self) -> Body {
+        Body {
+            position: vector![self.position.0, self.position.1],
+            radius: self.radius,
+            mass: self.mass,
+            solidity: Solidity::Solid
+        }
+    }
+
     pub fn set_facing(&mut self, facing: f32) {
         self.facing = facing;
         self.forward.0 = facing.cos();
@@ -43,45 +99,130 @@ impl Player {
         self.right.1 = (facing + PI / 2.0).sin();
     }
 
-    pub fn update(&mut self, map: &Map, input: &Input) {
-        self.velocity = (0.0, 0.0);
+    /// Returns a copy of the player between `previous` (at `alpha` 0.0) and `self` (at `alpha` 1.0)
+    pub fn interpolate(&self, previous: &Player, alpha: f32) -> Player {
+        let mut player = self.clone();
+        player.position.0 = previous.position.0 + (self.position.0 - previous.position.0) * alpha;
+        player.position.1 = previous.position.1 + (self.position.1 - previous.position.1) * alpha;
+
+        player.pitch = previous.pitch + (self.pitch - previous.pitch) * alpha;
+
+        player.set_facing(lerp_angle(previous.facing, self.facing, alpha));
+        player
+    }
+
+    /// Advances the player by `dt` seconds
+    pub fn update(&mut self, map: &Map, input: &TickInput, dt: f32) {
+        if input.action_pressed(Action::TurnRight) {
+            self.set_facing(self.facing + self.turn_speed * dt);
+        }
+        if input.action_pressed(Action::TurnLeft) {
+            self.set_facing(self.facing - self.turn_speed * dt);
+        }
+
+        let mouse_turn = input.mouse_delta.0 * self.yaw_sensitivity;
+        if mouse_turn != 0.0 {
+            self.set_facing(self.facing + if self.invert_mouse { -mouse_turn } else { mouse_turn });
+        }
+
+        let (look_x, look_y) = input.right_stick;
+        if look_x != 0.0 {
+            self.set_facing(self.facing + look_x * self.turn_speed * dt);
+        }
+
+        let look_pitch = input.mouse_delta.1 * self.pitch_sensitivity + look_y * self.pitch_speed * dt;
+        let look_pitch = if self.invert_pitch { look_pitch } else { -look_pitch };
+        self.pitch = (self.pitch + look_pitch).clamp(-MAX_PITCH, MAX_PITCH);
 
-        if input.get_pressed(Keycode::Right) {
-            self.set_facing(self.facing + self.turn_speed);
+        // Sum the pressed directions so diagonal movement works, without going faster diagonally
+        let mut wish = (0.0, 0.0);
+        if input.action_pressed(Action::MoveForward) {
+            wish.0 += self.forward.0;
+            wish.1 += self.forward.1;
+        }
+        if input.action_pressed(Action::MoveBackward) {
+            wish.0 -= self.forward.0;
+            wish.1 -= self.forward.1;
+        }
+        if input.action_pressed(Action::StrafeRight) {
+            wish.0 += self.right.0;
+            wish.1 += self.right.1;
         }
-        if input.get_pressed(Keycode::Left) {
-            self.set_facing(self.facing - self.turn_speed);
+        if input.action_pressed(Action::StrafeLeft) {
+            wish.0 -= self.right.0;
+            wish.1 -= self.right.1;
         }
 
-        if input.get_pressed(Keycode::W) {
-            // self.position.0 += self.forward.0 * self.speed;
-            // self.position.1 += self.forward.1 * self.speed;
-            self.velocity.0 = self.forward.0 * self.speed;
-            self.velocity.1 = self.forward.1 * self.speed;
+        // The left stick adds to the buttons, and can ask for less than full speed. Stick y points down
+        let (move_x, move_y) = input.left_stick;
+        wish.0 += self.forward.0 * -move_y + self.right.0 * move_x;
+        wish.1 += self.forward.1 * -move_y + self.right.1 * move_x;
+
+        let wish_length = (wish.0 * wish.0 + wish.1 * wish.1).sqrt();
+        if wish_length > 0.0 {
+            wish = (wish.0 / wish_length, wish.1 / wish_length);
         }
-        if input.get_pressed(Keycode::S) {
-            // self.position.0 -= self.forward.0 * self.speed;
-            // self.position.1 -= self.forward.1 * self.speed;
-            self.velocity.0 = -self.forward.0 * self.speed;
-            self.velocity.1 = -self.forward.1 * self.speed;
+
+        let max_speed = if input.action_pressed(Action::Sprint) {
+            self.speed * self.sprint_multiplier
+        } else {
+            self.speed
+        } * wish_length.min(1.0);
+
+        // Friction
+        let current_speed = (self.velocity.0 * self.velocity.0 + self.velocity.1 * self.velocity.1).sqrt();
+        if current_speed > 0.0 {
+            let new_speed = (current_speed - current_speed * self.friction * dt).max(0.0);
+            let new_speed = if new_speed < STOP_SPEED { 0.0 } else { new_speed };
+            self.velocity.0 *= new_speed / current_speed;
+            self.velocity.1 *= new_speed / current_speed;
         }
-        if input.get_pressed(Keycode::D) {
-            // self.position.0 += self.right.0 * self.speed;
-            // self.position.1 += self.right.1 * self.speed;
-            self.velocity.0 = self.right.0 * self.speed;
-            self.velocity.1 = self.right.1 * self.speed;
+
+        // Accelerate towards the wish direction, only up to `max_speed` along it
+        let speed_along_wish = self.velocity.0 * wish.0 + self.velocity.1 * wish.1;
+        let add_speed = max_speed - speed_along_wish;
+        if wish_length > 0.0 && add_speed > 0.0 {
+            let accel_speed = (self.acceleration * max_speed * dt).min(add_speed);
+            self.velocity.0 += wish.0 * accel_speed;
+            self.velocity.1 += wish.1 * accel_speed;
         }
-        if input.get_pressed(Keycode::A) {
-            // self.position.0 -= self.right.0 * self.speed;
-            // self.position.1 -= self.right.1 * self.speed;
-            self.velocity.0 = -self.right.0 * self.speed;
-            self.velocity.1 = -self.right.1 * self.speed;
+
+        let new_pos = collision::sweep_move(vector![self.position.0, self.position.1], self.radius, vector![self.velocity.0 * dt, self.velocity.1 * dt], &map.collision());
+
+        // Lose any velocity that went into walls
+        if dt > 0.0 {
+            self.velocity.0 = (new_pos.x - self.position.0) / dt;
+            self.velocity.1 = (new_pos.y - self.position.1) / dt;
         }
 
-        
-        let new_pos = collision::slide_move(vector![self.position.0, self.position.1], self.radius, vector![self.velocity.0, self.velocity.1], &map.segments);
-    
         self.position.0 = new_pos.x;
         self.position.1 = new_pos.y;
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn angles_interpolate_the_short_way_round() {
+        // From just below pi to just above -pi crosses pi, rather than going back through 0
+        let (from, to) = (PI - 0.1, -PI + 0.1);
+        assert!((lerp_angle(from, to, 0.5).rem_euclid(2.0 * PI) - PI).abs() < 1e-5);
+        assert!((lerp_angle(from, to, 1.0) - (PI + 0.1)).abs() < 1e-5);
+
+        assert!((lerp_angle(0.2, -0.2, 0.5)).abs() < 1e-6);
+        assert!((lerp_angle(0.0, 3.0 * PI / 2.0, 0.5) + PI / 4.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn interpolated_facing_crosses_pi_the_short_way() {
+        let mut previous = Player::new((0.0, 0.0));
+        previous.set_facing(PI - 0.1);
+        let mut current = previous.clone();
+        current.set_facing(-PI + 0.1);
+
+        // Halfway the player faces straight along -x, not along +x
+        let player = current.interpolate(&previous, 0.5);
+        assert!((player.forward.0 + 1.0).abs() < 1e-5 && player.forward.1.abs() < 1e-3, "{:?}", player.forward);
+    }
+}
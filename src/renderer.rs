@@ -0,0 +1,419 @@
+use core::f32::consts::PI;
+
+use sdl2::pixels::Color;
+
+use crate::map::{Map, RaycastResult};
+use crate::player::{Player, MAX_PITCH};
+use crate::sprite::Sprite;
+
+/// How ray directions and wall distances are derived for each screen column
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Projection {
+    /// Rays are spread across a camera plane and walls are scaled by perpendicular distance,
+    /// so straight walls stay straight
+    #[default]
+    Perspective,
+    /// Rays are spaced by equal angles and walls are scaled by euclidean distance,
+    /// which bows straight walls outwards
+    Fisheye
+}
+
+/// Software renderer that draws a frame into an RGBA8888 framebuffer in system memory,
+/// so frames can be produced without a window or GPU.
+pub struct Renderer {
+    pub width: u32,
+    pub height: u32,
+    pub framebuffer: Vec<u32>,
+    /// Depth of the wall drawn in each column, used to clip sprites
+    pub depth: Vec<f32>,
+    /// Transparent walls in front of the opaque one in each column, nearest first. They are
+    /// drawn after the opaque walls, interleaved with the sprites by depth
+    glass: Vec<Vec<RaycastResult>>,
+    pub projection: Projection,
+    /// Draw a top-down view of the map, its collision segments and the rays cast this frame
+    pub debug_overlay: bool
+}
+
+/// Most pixels a map cell takes up in the debug overlay
+const OVERLAY_CELL: f32 = 8.0;
+
+/// Packs `color` into an RGBA8888 pixel (`0xRRGGBBAA`)
+pub fn pack(color: Color) -> u32 {
+    ((color.r as u32) << 24) | ((color.g as u32) << 16) | ((color.b as u32) << 8) | color.a as u32
+}
+
+/// Unpacks an RGBA8888 pixel into a color
+pub fn unpack(pixel: u32) -> Color {
+    Color::RGBA((pixel >> 24) as u8, (pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8)
+}
+
+/// Darkens `pixel` by `shade` (1.0 leaves it unchanged, 0.0 is black), keeping its alpha
+pub fn shade_pixel(pixel: u32, shade: f32) -> u32 {
+    let c = unpack(pixel);
+    pack(Color::RGBA((c.r as f32 * shade) as u8, (c.g as f32 * shade) as u8, (c.b as f32 * shade) as u8, c.a))
+}
+
+/// Composites `src` over `dst` using the alpha of `src`
+pub fn blend(dst: u32, src: u32) -> u32 {
+    let s = unpack(src);
+    if s.a == 255 {
+        return src;
+    }
+
+    let d = unpack(dst);
+    let a = s.a as u32;
+    let mix = |s: u8, d: u8| ((s as u32 * a + d as u32 * (255 - a)) / 255) as u8;
+    pack(Color::RGBA(mix(s.r, d.r), mix(s.g, d.g), mix(s.b, d.b), d.a.max(s.a)))
+}
+
+impl Renderer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width, height,
+            framebuffer: vec![pack(Color::BLACK); (width * height) as usize],
+            depth: vec![f32::INFINITY; width as usize],
+            glass: (0..width).map(|_| Vec::new()).collect(),
+            projection: Projection::default(),
+            debug_overlay: false
+        }
+    }
+
+    /// Returns the framebuffer as bytes, with each pixel in native byte order
+    pub fn bytes(&self) -> Vec<u8> {
+        self.framebuffer.iter().flat_map(|p| p.to_ne_bytes()).collect()
+    }
+
+    /// Number of bytes per framebuffer row
+    pub fn pitch(&self) -> usize {
+        self.width as usize * 4
+    }
+
+    pub fn clear(&mut self, color: Color) {
+        self.framebuffer.fill(pack(color));
+    }
+
+    pub fn render(&mut self, map: &Map, player: &Player) {
+        let dirs: Vec<(f32, f32)> = (0..self.width).map(|col| self.column_dir(player, col)).collect();
+
+        self.clear(Color::BLACK);
+        self.draw_floor_ceiling(map, player, &dirs);
+        self.draw_walls(map, player, &dirs);
+        self.draw_sprites(map, player);
+        for col in 0..self.width {
+            self.draw_glass_behind(map, player, col, 0.0);
+        }
+
+        if self.debug_overlay {
+            self.draw_debug_overlay(map, player, &dirs);
+        }
+    }
+
+    /// Screen row of the horizon, shifted from the middle of the screen by the player's pitch
+    fn horizon(&self, player: &Player) -> f32 {
+        let pitch = player.pitch.clamp(-MAX_PITCH, MAX_PITCH);
+        (self.height as f32) / 2.0 + pitch * self.height as f32
+    }
+
+    /// Pixels on screen per unit of size one unit of depth away, the same horizontally and
+    /// vertically so pixels stay square. For the fisheye projection this holds in the middle of
+    /// the screen, where columns are spaced by `fov / width` radians
+    fn focal_length(&self, player: &Player) -> f32 {
+        match self.projection {
+            Projection::Perspective => (self.width as f32 / 2.0) / (player.fov / 2.0).tan(),
+            Projection::Fisheye => self.width as f32 / player.fov
+        }
+    }
+
+    /// Direction of the ray through screen column `col`. For the perspective projection its
+    /// component along `player.forward` is 1, so distances along it are perpendicular depths.
+    fn column_dir(&self, player: &Player, col: u32) -> (f32, f32) {
+        match self.projection {
+            Projection::Perspective => {
+                // Half width of the camera plane, one unit in front of the player
+                let plane = (player.fov / 2.0).tan();
+                let camera_x = 2.0 * (col as f32 + 0.5) / self.width as f32 - 1.0;
+                (player.forward.0 + player.right.0 * plane * camera_x, player.forward.1 + player.right.1 * plane * camera_x)
+            }
+            Projection::Fisheye => {
+                let angle = (player.facing - player.fov / 2.0) + (col as f32 / self.width as f32) * player.fov;
+                (angle.cos(), angle.sin())
+            }
+        }
+    }
+
+    fn draw_floor_ceiling(&mut self, map: &Map, player: &Player, dirs: &[(f32, f32)]) {
+        let (width, height) = (self.width, self.height);
+        let center = self.horizon(player);
+        let focal_length = self.focal_length(player);
+        let (floor_textured, ceiling_textured) = (map.has_floor_textures(), map.has_ceiling_textures());
+
+        for i in 0..height {
+            let is_ceiling = (i as f32) < center;
+            let (color, textured) = if is_ceiling {
+                (map.ceiling, ceiling_textured)
+            } else {
+                (map.floor, floor_textured)
+            };
+
+            // Projected height of a wall whose base or top lies on this row, and the distance
+            // to the point on the floor or ceiling seen through it
+            let line_height = (2.0 * (i as f32 - center)).abs();
+            let distance = focal_length / line_height;
+            let shade = map.shade(distance);
+            let flat = shade_pixel(pack(color), shade);
+            let row = (i * width) as usize;
+
+            if !textured || line_height == 0.0 {
+                self.framebuffer[row..row + width as usize].fill(flat);
+                continue;
+            }
+
+            for (col, dir) in dirs.iter().enumerate() {
+                let x = player.position.0 + dir.0 * distance;
+                let y = player.position.1 + dir.1 * distance;
+
+                let id = match map.cell_at(x, y) {
+                    Some((cell_x, cell_y)) if is_ceiling => map.get_ceiling(cell_x, cell_y),
+                    Some((cell_x, cell_y)) => map.get_floor(cell_x, cell_y),
+                    None => 0
+                };
+
+                self.framebuffer[row + col] = if id == 0 {
+                    flat
+                } else {
+                    shade_pixel(map.textures[id as usize - 1].sample(x / map.cell_size, y / map.cell_size), shade)
+                };
+            }
+        }
+    }
+
+    /// Draws the opaque wall seen through each column and keeps the transparent walls in front
+    /// of it for later. The depth buffer records the opaque wall, so sprites behind glass stay
+    /// visible, and get the glass drawn over them
+    fn draw_walls(&mut self, map: &Map, player: &Player, dirs: &[(f32, f32)]) {
+        self.depth.fill(f32::INFINITY);
+
+        for (col, dir) in dirs.iter().enumerate() {
+            let mut hits = map.cast_ray_through(
+                player.position.0,
+                player.position.1,
+                dir.0,
+                dir.1
+            );
+
+            if hits.last().is_some_and(|hit| !map.is_transparent(map.get(hit.cell.0, hit.cell.1))) {
+                let wall = hits.pop().unwrap();
+                self.draw_wall_hit(map, player, col as u32, &wall);
+            }
+            self.glass[col] = hits;
+        }
+    }
+
+    /// Draws the transparent walls in a column that are further away than `depth`, back to front
+    fn draw_glass_behind(&mut self, map: &Map, player: &Player, col: u32, depth: f32) {
+        while let Some(hit) = self.glass[col as usize].pop() {
+            if self.hit_depth(player, &hit).1 <= depth {
+                self.glass[col as usize].push(hit);
+                break;
+            }
+
+            self.draw_wall_hit(map, player, col, &hit);
+        }
+    }
+
+    /// Distance to a wall hit, and the depth its height on screen is derived from
+    fn hit_depth(&self, player: &Player, result: &RaycastResult) -> (f32, f32) {
+        let offset = (result.pos.0 - player.position.0, result.pos.1 - player.position.1);
+        let distance = (offset.0.powf(2.0) + offset.1.powf(2.0)).sqrt();
+
+        match self.projection {
+            Projection::Perspective => (distance, offset.0 * player.forward.0 + offset.1 * player.forward.1),
+            Projection::Fisheye => (distance, distance)
+        }
+    }
+
+    fn draw_wall_hit(&mut self, map: &Map, player: &Player, col: u32, result: &RaycastResult) {
+        let (width, height) = (self.width, self.height);
+        let center = self.horizon(player);
+
+        let (distance, depth) = self.hit_depth(player, result);
+        let shade = map.shade(distance);
+
+        let line_height = if depth <= 0.0 {
+            height as f32
+        } else {
+            self.focal_length(player) / depth
+        };
+
+        let tile = map.get(result.cell.0, result.cell.1);
+        if !map.is_transparent(tile) {
+            self.depth[col as usize] = depth;
+        }
+
+        let texture = &map.textures[map.tile(tile).texture(result.face)];
+
+        // Clip the column to the screen, keeping texture coordinates relative to the full wall
+        let start = center - line_height / 2.0;
+        let y0 = start.max(0.0) as u32;
+        let y1 = (start + line_height).min(height as f32) as u32;
+
+        for y in y0..y1 {
+            let v = (y as f32 + 0.5 - start) / line_height;
+            let index = (y * width + col) as usize;
+            self.framebuffer[index] = blend(self.framebuffer[index], shade_pixel(texture.sample(result.u, v), shade));
+        }
+    }
+
+    /// Returns the depth and screen column of the center of a world position, or `None` if it is behind the camera
+    fn project(&self, player: &Player, position: (f32, f32)) -> Option<(f32, f32)> {
+        let offset = (position.0 - player.position.0, position.1 - player.position.1);
+
+        match self.projection {
+            Projection::Perspective => {
+                let depth = offset.0 * player.forward.0 + offset.1 * player.forward.1;
+                let lateral = offset.0 * player.right.0 + offset.1 * player.right.1;
+                if depth <= 0.0 {
+                    return None;
+                }
+
+                Some((depth, self.width as f32 / 2.0 + self.focal_length(player) * lateral / depth))
+            }
+            Projection::Fisheye => {
+                let depth = (offset.0.powf(2.0) + offset.1.powf(2.0)).sqrt();
+                let angle = (offset.1.atan2(offset.0) - player.facing + PI).rem_euclid(2.0 * PI) - PI;
+                if depth <= 0.0 || angle.abs() >= PI / 2.0 {
+                    return None;
+                }
+
+                Some((depth, (angle + player.fov / 2.0) / player.fov * self.width as f32))
+            }
+        }
+    }
+
+    /// Draws `map.sprites` back to front, skipping columns where a wall is closer. Glass further
+    /// away than a sprite is drawn before it in each column
+    fn draw_sprites(&mut self, map: &Map, player: &Player) {
+        let (width, height) = (self.width, self.height);
+        let center = self.horizon(player);
+        let focal_length = self.focal_length(player);
+
+        let mut visible: Vec<(f32, f32, &Sprite)> = map.sprites.iter()
+            .filter_map(|sprite| self.project(player, sprite.position).map(|(depth, x)| (depth, x, sprite)))
+            .collect();
+        visible.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        for (depth, screen_x, sprite) in visible {
+            let texture = &map.textures[sprite.texture];
+            let distance = ((sprite.position.0 - player.position.0).powf(2.0) + (sprite.position.1 - player.position.1).powf(2.0)).sqrt();
+            let shade = map.shade(distance);
+
+            // Sprites are square and stand on the floor
+            let size = focal_length / depth * sprite.scale;
+            let left = screen_x - size / 2.0;
+            let bottom = center + focal_length / depth / 2.0;
+            let top = bottom - size;
+
+            let x0 = left.max(0.0) as u32;
+            let x1 = (left + size).min(width as f32).max(0.0) as u32;
+            let y0 = top.max(0.0) as u32;
+            let y1 = bottom.min(height as f32).max(0.0) as u32;
+
+            for col in x0..x1 {
+                if depth >= self.depth[col as usize] {
+                    continue;
+                }
+                self.draw_glass_behind(map, player, col, depth);
+
+                let u = (col as f32 + 0.5 - left) / size;
+                for y in y0..y1 {
+                    let texel = texture.sample(u, (y as f32 + 0.5 - top) / size);
+                    if texel & 0xff == 0 {
+                        continue;
+                    }
+
+                    let index = (y * width + col) as usize;
+                    self.framebuffer[index] = blend(self.framebuffer[index], shade_pixel(texel, shade));
+                }
+            }
+        }
+    }
+
+    /// Draws a top-down view of the map into the top left corner: walls, collision segments in
+    /// red, the player's collision circle in green and where some of the columns' rays hit in
+    /// yellow. Walls and collision both come from `Map::cell_origin`, so the red outlines should
+    /// sit exactly on the edges of the walls and the ray hits
+    fn draw_debug_overlay(&mut self, map: &Map, player: &Player, dirs: &[(f32, f32)]) {
+        const MARGIN: f32 = 4.0;
+
+        // Pixels per world unit, shrunk so the overlay takes up at most a third of the screen
+        let cell = OVERLAY_CELL.min(self.width as f32 / 3.0 / map.width as f32);
+        let scale = cell / map.cell_size;
+        let to_screen = |x: f32, y: f32| (MARGIN + x * scale, MARGIN + y * scale);
+
+        let (right, bottom) = to_screen(map.width as f32 * map.cell_size, map.height as f32 * map.cell_size);
+        self.overlay_rect((MARGIN, MARGIN), (right, bottom), 0x000000a0);
+
+        for y in 0..map.height {
+            for x in 0..map.width {
+                let tile = map.get(x, y);
+                if tile == 0 {
+                    continue;
+                }
+
+                let (ox, oy) = map.cell_origin(x, y);
+                let color = if map.is_transparent(tile) || !map.is_solid(tile) { 0x8080c0a0 } else { 0xb0b0b0c0 };
+                self.overlay_rect(to_screen(ox, oy), to_screen(ox + map.cell_size, oy + map.cell_size), color);
+            }
+        }
+
+        for (a, b) in map.collision_segments() {
+            self.overlay_line(to_screen(a.x, a.y), to_screen(b.x, b.y), 0xff2020ff);
+        }
+
+        let (px, py) = player.position;
+        for dir in dirs.iter().step_by(16) {
+            if let Some(hit) = map.cast_ray(px, py, dir.0, dir.1) {
+                self.overlay_line(to_screen(px, py), to_screen(hit.pos.0, hit.pos.1), 0xffff0060);
+                let (hx, hy) = to_screen(hit.pos.0, hit.pos.1);
+                self.overlay_rect((hx - 1.0, hy - 1.0), (hx + 1.0, hy + 1.0), 0xffff00ff);
+            }
+        }
+
+        let steps = 24;
+        for i in 0..steps {
+            let (a0, a1) = (i as f32 / steps as f32 * 2.0 * PI, (i + 1) as f32 / steps as f32 * 2.0 * PI);
+            let p0 = to_screen(px + a0.cos() * player.radius, py + a0.sin() * player.radius);
+            let p1 = to_screen(px + a1.cos() * player.radius, py + a1.sin() * player.radius);
+            self.overlay_line(p0, p1, 0x20ff20ff);
+        }
+        let facing = to_screen(px + player.forward.0 * player.radius * 2.0, py + player.forward.1 * player.radius * 2.0);
+        self.overlay_line(to_screen(px, py), facing, 0x20ff20ff);
+    }
+
+    /// Blends `color` into the pixel at `x`, `y` if it is on screen
+    fn overlay_pixel(&mut self, x: i32, y: i32, color: u32) {
+        if x >= 0 && y >= 0 && x < self.width as i32 && y < self.height as i32 {
+            let index = (y as u32 * self.width + x as u32) as usize;
+            self.framebuffer[index] = blend(self.framebuffer[index], color);
+        }
+    }
+
+    /// Fills the pixels whose centers lie between the `from` and `to` corners
+    fn overlay_rect(&mut self, from: (f32, f32), to: (f32, f32), color: u32) {
+        for y in (from.1 - 0.5).ceil() as i32..(to.1 - 0.5).ceil() as i32 {
+            for x in (from.0 - 0.5).ceil() as i32..(to.0 - 0.5).ceil() as i32 {
+                self.overlay_pixel(x, y, color);
+            }
+        }
+    }
+
+    fn overlay_line(&mut self, from: (f32, f32), to: (f32, f32), color: u32) {
+        let steps = (to.0 - from.0).abs().max((to.1 - from.1).abs()).ceil().max(1.0) as i32;
+        for i in 0..=steps {
+            let t = i as f32 / steps as f32;
+            let x = from.0 + (to.0 - from.0) * t;
+            let y = from.1 + (to.1 - from.1) * t;
+            self.overlay_pixel(x.floor() as i32, y.floor() as i32, color);
+        }
+    }
+}
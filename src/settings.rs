@@ -0,0 +1,103 @@
+use std::path::Path;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::player::Player;
+
+/// How the player likes looking around, kept in a config file next to the bindings. Anything left
+/// out of the file keeps its default
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Radians turned per pixel of horizontal mouse movement
+    pub yaw_sensitivity: f32,
+    /// Screen heights looked up or down per pixel of vertical mouse movement
+    pub pitch_sensitivity: f32,
+    /// Turn left when the mouse moves right
+    pub invert_mouse: bool,
+    /// Look up when the mouse or right stick moves down
+    pub invert_pitch: bool
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            yaw_sensitivity: 0.003,
+            pitch_sensitivity: 0.003,
+            invert_mouse: false,
+            invert_pitch: false
+        }
+    }
+}
+
+impl Settings {
+    /// Gives `player` these settings
+    pub fn apply(&self, player: &mut Player) {
+        player.yaw_sensitivity = self.yaw_sensitivity;
+        player.pitch_sensitivity = self.pitch_sensitivity;
+        player.invert_mouse = self.invert_mouse;
+        player.invert_pitch = self.invert_pitch;
+    }
+
+    pub fn parse(string: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(string)?)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let string = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read settings {}", path.display()))?;
+
+        Self::parse(&string)
+            .with_context(|| format!("failed to parse settings {}", path.display()))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let string = toml::to_string(self)?;
+
+        std::fs::write(path, string)
+            .with_context(|| format!("failed to write settings {}", path.display()))
+    }
+
+    /// Loads settings from `path`, or writes the defaults there if it doesn't exist yet
+    pub fn load_or_create<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if path.exists() {
+            return Self::load(path);
+        }
+
+        let settings = Self::default();
+        settings.save(path)?;
+        Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_settings_keep_their_defaults() {
+        let settings = Settings::parse("pitch_sensitivity = 0.01\ninvert_pitch = true\n").unwrap();
+        assert_eq!(settings, Settings { pitch_sensitivity: 0.01, invert_pitch: true, ..Settings::default() });
+
+        assert_eq!(Settings::parse("").unwrap(), Settings::default());
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let settings = Settings { yaw_sensitivity: 0.002, pitch_sensitivity: 0.005, invert_mouse: true, invert_pitch: false };
+        assert_eq!(Settings::parse(&toml::to_string(&settings).unwrap()).unwrap(), settings);
+    }
+
+    #[test]
+    fn applies_to_the_player() {
+        let settings = Settings { yaw_sensitivity: 0.002, pitch_sensitivity: 0.005, invert_mouse: true, invert_pitch: true };
+        let mut player = Player::new((0.0, 0.0));
+        settings.apply(&mut player);
+        assert_eq!((player.yaw_sensitivity, player.pitch_sensitivity), (0.002, 0.005));
+        assert!(player.invert_mouse && player.invert_pitch);
+    }
+}
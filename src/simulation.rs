@@ -0,0 +1,47 @@
+use crate::bindings::Action;
+use crate::collision::{self, Solidity};
+use crate::input::TickInput;
+use crate::map::Map;
+use crate::player::Player;
+use crate::sprite::Sprite;
+
+/// How far away the player can use doors from
+pub const USE_REACH: f32 = 1.5;
+
+/// Advances the world by one tick of `dt` seconds. Everything that affects gameplay goes through
+/// here, so that replaying the same inputs gives the same results. Returns the trigger sprites
+/// the player is touching, as indices into `Map::sprites`
+pub fn step(map: &mut Map, player: &mut Player, input: &TickInput, dt: f32) -> Vec<usize> {
+    if input.action_just_pressed(Action::Use) {
+        map.use_door(player.position.0, player.position.1, player.forward.0, player.forward.1, USE_REACH);
+    }
+    map.update_doors(dt, player.position, player.radius);
+    player.update(map, input, dt);
+    push_bodies(map, player, dt)
+}
+
+/// Separates the player and solid sprites that ended up overlapping
+fn push_bodies(map: &mut Map, player: &mut Player, dt: f32) -> Vec<usize> {
+    // Nothing to do in the common case of a level with only decorations
+    if map.sprites.iter().all(|sprite| sprite.solidity == Solidity::None) {
+        return Vec::new();
+    }
+
+    let mut bodies = vec![player.body()];
+    bodies.extend(map.sprites.iter().map(Sprite::body));
+    let touching = collision::resolve_bodies(&mut bodies, &map.collision());
+
+    // Like walls, whatever pushed the player back takes away the velocity that went into it
+    let pushed = (bodies[0].position.x - player.position.0, bodies[0].position.y - player.position.1);
+    player.position = (bodies[0].position.x, bodies[0].position.y);
+    if dt > 0.0 {
+        player.velocity.0 += pushed.0 / dt;
+        player.velocity.1 += pushed.1 / dt;
+    }
+
+    for (sprite, body) in map.sprites.iter_mut().zip(&bodies[1..]) {
+        sprite.position = (body.position.x, body.position.y);
+    }
+
+    touching.into_iter().filter(|&(a, _)| a == 0).map(|(_, b)| b - 1).collect()
+}
@@ -0,0 +1,40 @@
+use nalgebra::vector;
+
+use crate::collision::{Body, Solidity};
+
+/// A camera-facing billboard placed in the world
+#[derive(Clone, Copy, Debug)]
+pub struct Sprite {
+    pub position: (f32, f32),
+    /// Index into `Map::textures`
+    pub texture: usize,
+    /// Height relative to a wall, the sprite always stands on the floor
+    pub scale: f32,
+    /// Radius of the circle the sprite collides as
+    pub radius: f32,
+    /// Infinite for sprites that can't be pushed around
+    pub mass: f32,
+    pub solidity: Solidity
+}
+
+impl Sprite {
+    pub fn new(position: (f32, f32), texture: usize) -> Self {
+        Self {
+            position,
+            texture,
+            scale: 1.0,
+            radius: 0.25,
+            mass: f32::INFINITY,
+            solidity: Solidity::None
+        }
+    }
+
+    pub fn body(&self) -> Body {
+        Body {
+            position: vector![self.position.0, self.position.1],
+            radius: self.radius,
+            mass: self.mass,
+            solidity: self.solidity
+        }
+    }
+}
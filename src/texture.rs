@@ -3,33 +3,84 @@
 use std::path::Path;
 
 use anyhow::anyhow;
-use sdl2::{image::LoadSurface, render::TextureCreator, surface::Surface};
+use sdl2::{image::LoadSurface, pixels::PixelFormatEnum, surface::Surface};
 
-pub struct Texture<'a> {
-    pub inner: sdl2::render::Texture<'a>,
+/// A texture stored in system memory so it can be sampled by the software renderer.
+/// Pixels are packed RGBA8888 (`0xRRGGBBAA`), row-major.
+pub struct Texture {
+    pub pixels: Vec<u32>,
     pub width: u32,
     pub height: u32
 }
 
-impl<'a> Texture<'a> {
-    pub fn new<T>(surface: Surface<'a>, creator: &'a TextureCreator<T>) -> anyhow::Result<Self> {
-        let surf_width = surface.width();
-        let surf_height = surface.height();
+impl Texture {
+    pub fn new(surface: Surface) -> anyhow::Result<Self> {
+        let surface = surface.convert_format(PixelFormatEnum::RGBA8888).map_err(|e| anyhow!("failed to load texture: {}", e))?;
+        let width = surface.width();
+        let height = surface.height();
+        if width == 0 || height == 0 {
+            return Err(anyhow!("texture is empty ({}x{})", width, height));
+        }
+        let pitch = surface.pitch() as usize;
+
+        let pixels = surface.with_lock(|bytes| {
+            let mut pixels = Vec::with_capacity((width * height) as usize);
+            for y in 0..height as usize {
+                let row = &bytes[y * pitch..y * pitch + width as usize * 4];
+                pixels.extend(row.chunks_exact(4).map(|p| u32::from_ne_bytes([p[0], p[1], p[2], p[3]])));
+            }
+            pixels
+        });
 
-        Ok(Self {
-            inner: creator.create_texture_from_surface(surface).map_err(|e| anyhow!("failed to load texture: {}", e))?,
-            height: surf_height,
-            width: surf_width
-        })
+        Ok(Self { pixels, width, height })
     }
 
-    pub fn from_file<T, P: AsRef<Path>>(file: P, creator: &'a TextureCreator<T>) -> anyhow::Result<Self> {
+    pub fn from_file<P: AsRef<Path>>(file: P) -> anyhow::Result<Self> {
         let surface = Surface::from_file(file);
 
         if let Ok(surf) = surface {
-            Ok(Self::new(surf, creator)?)
+            Self::new(surf)
         } else {
             Err(anyhow::Error::msg(surface.err().unwrap_or("failed to load texture".to_string())))
         }
     }
-}
\ No newline at end of file
+
+    /// Creates a texture from already decoded RGBA8888 pixels
+    pub fn from_pixels(pixels: Vec<u32>, width: u32, height: u32) -> anyhow::Result<Self> {
+        if width == 0 || height == 0 {
+            return Err(anyhow!("texture is empty ({}x{})", width, height));
+        }
+        if pixels.len() != (width * height) as usize {
+            return Err(anyhow!("expected {} pixels for a {}x{} texture, got {}", width * height, width, height, pixels.len()));
+        }
+
+        Ok(Self { pixels, width, height })
+    }
+
+    /// Returns the pixel at texture coordinates `u`, `v` in `0.0..1.0`, wrapping outside that range
+    pub fn sample(&self, u: f32, v: f32) -> u32 {
+        let x = ((u.rem_euclid(1.0) * self.width as f32) as u32).min(self.width - 1);
+        let y = ((v.rem_euclid(1.0) * self.height as f32) as u32).min(self.height - 1);
+        self.pixels[(y * self.width + x) as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_textures_are_rejected() {
+        assert!(Texture::from_pixels(Vec::new(), 0, 0).is_err());
+        assert!(Texture::from_pixels(Vec::new(), 4, 0).is_err());
+        assert!(Texture::from_pixels(Vec::new(), 0, 4).is_err());
+    }
+
+    #[test]
+    fn sampling_wraps_around() {
+        let texture = Texture::from_pixels(vec![1, 2, 3, 4], 2, 2).unwrap();
+        assert_eq!(texture.sample(0.25, 0.25), 1);
+        assert_eq!(texture.sample(0.75, 0.75), 4);
+        assert_eq!(texture.sample(1.25, -0.25), 3);
+    }
+}
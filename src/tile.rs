@@ -0,0 +1,58 @@
+/// Side of a cell. The grid's y axis points south, so the north face is the one at the lowest y
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Face {
+    North,
+    South,
+    East,
+    West
+}
+
+impl Face {
+    /// Outward facing unit normal of this face
+    pub fn normal(&self) -> (f32, f32) {
+        match self {
+            Face::North => (0.0, -1.0),
+            Face::South => (0.0, 1.0),
+            Face::East => (1.0, 0.0),
+            Face::West => (-1.0, 0.0)
+        }
+    }
+}
+
+/// Describes how a type of cell looks and behaves
+#[derive(Clone, Copy, Debug)]
+pub struct TileDef {
+    /// Indices into `Map::textures` for each face
+    pub north: usize,
+    pub south: usize,
+    pub east: usize,
+    pub west: usize,
+    /// Whether the tile blocks movement
+    pub solid: bool,
+    /// Whether rays continue past the tile, showing what is behind it where its textures are transparent
+    pub transparent: bool
+}
+
+impl TileDef {
+    /// A solid, opaque tile with the same texture on every face
+    pub fn uniform(texture: usize) -> Self {
+        Self {
+            north: texture,
+            south: texture,
+            east: texture,
+            west: texture,
+            solid: true,
+            transparent: false
+        }
+    }
+
+    /// Returns the texture of `face`
+    pub fn texture(&self, face: Face) -> usize {
+        match face {
+            Face::North => self.north,
+            Face::South => self.south,
+            Face::East => self.east,
+            Face::West => self.west
+        }
+    }
+}
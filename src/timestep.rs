@@ -0,0 +1,87 @@
+/// Accumulates real frame time and splits it into fixed simulation ticks, so gameplay runs at
+/// the same speed regardless of frame rate.
+pub struct FixedTimestep {
+    /// Seconds per simulation tick
+    pub dt: f32,
+    /// Longest frame that will be simulated, so a stall doesn't cause a burst of ticks
+    pub max_frame_time: f32,
+    accumulator: f32
+}
+
+impl FixedTimestep {
+    pub fn new(ticks_per_second: f32) -> Self {
+        Self {
+            dt: 1.0 / ticks_per_second,
+            max_frame_time: 0.25,
+            accumulator: 0.0
+        }
+    }
+
+    /// Adds `frame_time` seconds of real time
+    pub fn advance(&mut self, frame_time: f32) {
+        self.accumulator += frame_time.min(self.max_frame_time);
+    }
+
+    /// Consumes one tick of accumulated time, returning false once there isn't a whole tick left
+    pub fn tick(&mut self) -> bool {
+        if self.accumulator >= self.dt {
+            self.accumulator -= self.dt;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// How far between the previous and the current tick the rendered frame lies, from 0.0 to 1.0
+    pub fn alpha(&self) -> f32 {
+        self.accumulator / self.dt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ticks(timestep: &mut FixedTimestep) -> usize {
+        std::iter::from_fn(|| timestep.tick().then_some(())).count()
+    }
+
+    #[test]
+    fn frames_are_split_into_whole_ticks() {
+        // A tick rate whose tick length is exact in binary, so the counts don't depend on rounding
+        let mut timestep = FixedTimestep::new(64.0);
+
+        timestep.advance(3.0 / 64.0);
+        assert_eq!(ticks(&mut timestep), 3);
+
+        // Leftover time carries over to the next frame
+        timestep.advance(0.5 / 64.0);
+        assert_eq!(ticks(&mut timestep), 0);
+        timestep.advance(0.5 / 64.0);
+        assert_eq!(ticks(&mut timestep), 1);
+    }
+
+    #[test]
+    fn alpha_is_the_fraction_of_a_tick_left_over() {
+        let mut timestep = FixedTimestep::new(64.0);
+        assert_eq!(timestep.alpha(), 0.0);
+
+        timestep.advance(2.25 / 64.0);
+        ticks(&mut timestep);
+        assert_eq!(timestep.alpha(), 0.25);
+
+        // Always somewhere in between ticks, whatever the frame times
+        for i in 0..100 {
+            timestep.advance((i % 7) as f32 * 0.003);
+            ticks(&mut timestep);
+            assert!((0.0..1.0).contains(&timestep.alpha()), "alpha {}", timestep.alpha());
+        }
+    }
+
+    #[test]
+    fn long_frames_are_clamped() {
+        let mut timestep = FixedTimestep::new(64.0);
+        timestep.advance(10.0);
+        assert_eq!(ticks(&mut timestep), (timestep.max_frame_time * 64.0) as usize);
+    }
+}
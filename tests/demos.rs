@@ -0,0 +1,95 @@
+use ray_tracer::bindings::Action;
+use ray_tracer::demo::{Demo, DemoEnd};
+use ray_tracer::input::TickInput;
+use ray_tracer::level::Level;
+use ray_tracer::settings::Settings;
+use ray_tracer::simulation;
+
+/// Furthest a committed demo may end from where it was recorded. Float results can differ in the
+/// last bits between compilers and targets
+const EPSILON: f32 = 1e-4;
+
+fn close(a: DemoEnd, b: DemoEnd) -> bool {
+    (a.position.0 - b.position.0).abs() < EPSILON
+        && (a.position.1 - b.position.1).abs() < EPSILON
+        && (a.facing - b.facing).abs() < EPSILON
+        && (a.pitch - b.pitch).abs() < EPSILON
+}
+
+/// Replays every committed demo and checks the player ends up where it did when recording
+#[test]
+fn recorded_demos_replay() {
+    let mut count = 0;
+    for entry in std::fs::read_dir("res/demos").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|ext| ext != "demo") {
+            continue;
+        }
+
+        let demo = Demo::load(&path).unwrap();
+        let level = Level::load_untextured(&demo.level).unwrap();
+        let mut player = level.spawn_player();
+        let mut map = level.map;
+
+        demo.replay(&mut map, &mut player);
+        let end = DemoEnd::new(&player);
+        let expected = demo.end.unwrap_or_else(|| panic!("{} has no end to check against", path.display()));
+        assert!(close(end, expected), "{} diverged: ended at {:?}, recorded {:?}", path.display(), end, expected);
+        count += 1;
+    }
+
+    assert!(count > 0, "no demos found");
+}
+
+/// Within one build the simulation is deterministic, so replaying a demo recorded moments ago ends
+/// up bit for bit where the recording did
+#[test]
+fn fresh_recordings_replay_exactly() {
+    let level_path = "res/levels/default.toml";
+    let settings = Settings { yaw_sensitivity: 0.002, invert_pitch: true, ..Settings::default() };
+    let mut demo = Demo::new(level_path, 60.0, settings);
+
+    let level = Level::load_untextured(level_path).unwrap();
+    let mut player = level.spawn_player();
+    settings.apply(&mut player);
+    let mut map = level.map;
+
+    let walk = TickInput { pressed: Action::MoveForward.bit() | Action::Sprint.bit(), ..Default::default() };
+    for tick in 0..240 {
+        let input = match tick % 60 {
+            0 => TickInput { just_pressed: Action::Use.bit(), mouse_delta: (40.0, -12.0), ..walk },
+            1..30 => TickInput { left_stick: (0.5, -0.25), right_stick: (0.1, 0.3), ..walk },
+            _ => TickInput { pressed: Action::StrafeLeft.bit(), ..Default::default() }
+        };
+        demo.record(input);
+        simulation::step(&mut map, &mut player, &input, 1.0 / demo.ticks_per_second);
+    }
+    demo.finish(&player);
+
+    // Through the file format too, so nothing is lost saving it
+    let demo = Demo::from_bytes(&demo.to_bytes()).unwrap();
+    let level = Level::load_untextured(level_path).unwrap();
+    let mut player = level.spawn_player();
+    let mut map = level.map;
+    demo.replay(&mut map, &mut player);
+
+    assert_eq!(Some(DemoEnd::new(&player)), demo.end);
+}
+
+/// The door run has to make it through the door to the east side of the map
+#[test]
+fn door_run_passes_door() {
+    let demo = Demo::load("res/demos/door_run.demo").unwrap();
+    let level = Level::load_untextured(&demo.level).unwrap();
+    let mut player = level.spawn_player();
+    let mut map = level.map;
+
+    let dt = 1.0 / demo.ticks_per_second;
+    let mut furthest = player.position.0;
+    for input in demo.frames.iter() {
+        simulation::step(&mut map, &mut player, input, dt);
+        furthest = furthest.max(player.position.0);
+    }
+
+    assert!(furthest > 9.0, "only got to x = {}", furthest);
+}
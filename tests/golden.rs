@@ -0,0 +1,253 @@
+//! Renders a fixed level from fixed viewpoints and compares the frames against the reference
+//! images in `tests/golden`. Set `UPDATE_GOLDEN=1` to write new references after an intended
+//! change to the output; on a mismatch the actual frame and a diff image are written next to
+//! the other test artifacts in the target directory.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use ray_tracer::level::LevelFile;
+use ray_tracer::map::Map;
+use ray_tracer::player::Player;
+use ray_tracer::renderer::{unpack, Projection, Renderer};
+use ray_tracer::texture::Texture;
+
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 120;
+
+/// Largest difference in any channel for two pixels to count as the same
+const CHANNEL_TOLERANCE: u8 = 3;
+/// Fraction of pixels that may differ before a frame fails
+const MAX_MISMATCHED: f32 = 0.002;
+
+const LEVEL: &str = r##"
+textures = ["checker", "bricks", "glass", "tiles", "ball"]
+floor = [40, 60, 40]
+ceiling = [90, 90, 120]
+grid = """
+########
+#......#
+#..G...#
+#......D
+#...#..#
+########"""
+legend = { "." = 0, "#" = 1, "G" = 2, "D" = 3 }
+floor_grid = """
+00000000
+04444440
+04444440
+04444440
+04444440
+00000000"""
+
+[spawn]
+position = [3.5, 3.5]
+
+[[tiles]]
+texture = 1
+south = 2
+
+[[tiles]]
+texture = 3
+transparent = true
+
+[[tiles]]
+texture = 2
+door = true
+
+[[sprites]]
+position = [6.5, 3.5]
+texture = 5
+"##;
+
+/// Textures are generated rather than loaded, so the tests don't depend on image decoding
+fn texture(name: &str) -> Texture {
+    const SIZE: u32 = 32;
+    let pixel = |x: u32, y: u32| -> u32 {
+        match name {
+            "checker" => if (x / 8 + y / 8).is_multiple_of(2) { 0xc04040ff } else { 0xe0d0b0ff },
+            "bricks" => {
+                let offset = if (y / 8).is_multiple_of(2) { 0 } else { 8 };
+                if y.is_multiple_of(8) || (x + offset).is_multiple_of(16) { 0x505050ff } else { 0xa05030ff }
+            }
+            "glass" => {
+                if x < 2 || y < 2 || x >= SIZE - 2 || y >= SIZE - 2 { 0x202020ff } else { 0x80c0ff60 }
+            }
+            "tiles" => {
+                let v = (x * 4 + y * 4) as u8;
+                u32::from_be_bytes([v, 255 - v, 128, 255])
+            }
+            "ball" => {
+                let (dx, dy) = (x as f32 - 15.5, y as f32 - 15.5);
+                if dx * dx + dy * dy < 14.0 * 14.0 { 0xf0e020ff } else { 0 }
+            }
+            _ => panic!("unknown texture {}", name)
+        }
+    };
+
+    let pixels = (0..SIZE * SIZE).map(|i| pixel(i % SIZE, i / SIZE)).collect();
+    Texture::from_pixels(pixels, SIZE, SIZE).unwrap()
+}
+
+fn level() -> Map {
+    let file = LevelFile::parse(LEVEL).unwrap();
+    let mut map = file.build_map().unwrap();
+    for name in file.textures.iter() {
+        map.add_texture(texture(name.to_str().unwrap()));
+    }
+    map
+}
+
+fn view(position: (f32, f32), facing: f32, pitch: f32) -> Player {
+    let mut player = Player::new(position);
+    player.set_facing(facing.to_radians());
+    player.pitch = pitch;
+    player
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.png", name))
+}
+
+fn read_png(path: &Path) -> (u32, u32, Vec<u8>) {
+    let decoder = png::Decoder::new(File::open(path).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).unwrap();
+    assert_eq!((info.color_type, info.bit_depth), (png::ColorType::Rgba, png::BitDepth::Eight), "{} is not 8 bit RGBA", path.display());
+    buf.truncate(info.buffer_size());
+    (info.width, info.height, buf)
+}
+
+fn write_png(path: &Path, width: u32, height: u32, rgba: &[u8]) {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path).unwrap()), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header().unwrap().write_image_data(rgba).unwrap();
+}
+
+/// Renders `player`'s view of the test level and compares it with the reference called `name`
+fn check(name: &str, player: &Player, projection: Projection) {
+    let mut renderer = Renderer::new(WIDTH, HEIGHT);
+    renderer.projection = projection;
+    check_with(name, player, renderer);
+}
+
+fn check_with(name: &str, player: &Player, mut renderer: Renderer) {
+    renderer.render(&level(), player);
+    let actual: Vec<u8> = renderer.framebuffer.iter().flat_map(|p| p.to_be_bytes()).collect();
+
+    let path = golden_path(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        write_png(&path, WIDTH, HEIGHT, &actual);
+        return;
+    }
+
+    assert!(path.exists(), "missing reference {}, run with UPDATE_GOLDEN=1 to create it", path.display());
+    let (width, height, expected) = read_png(&path);
+    assert_eq!((width, height), (WIDTH, HEIGHT), "reference {} has the wrong size", path.display());
+
+    // Differing pixels are red in the diff, everything else a faded copy of the reference
+    let mut mismatched = 0;
+    let mut diff = Vec::with_capacity(expected.len());
+    for (e, a) in expected.chunks(4).zip(actual.chunks(4)) {
+        let delta = e.iter().zip(a).map(|(e, a)| e.abs_diff(*a)).max().unwrap();
+        if delta > CHANNEL_TOLERANCE {
+            mismatched += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            let grey = ((e[0] as u32 + e[1] as u32 + e[2] as u32) / 12) as u8;
+            diff.extend_from_slice(&[grey, grey, grey, 255]);
+        }
+    }
+
+    let fraction = mismatched as f32 / (WIDTH * HEIGHT) as f32;
+    if fraction > MAX_MISMATCHED {
+        let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+        std::fs::create_dir_all(&dir).unwrap();
+        let actual_path = dir.join(format!("{}.actual.png", name));
+        let diff_path = dir.join(format!("{}.diff.png", name));
+        write_png(&actual_path, WIDTH, HEIGHT, &actual);
+        write_png(&diff_path, WIDTH, HEIGHT, &diff);
+
+        panic!("{} differs from its reference in {} pixels ({:.2}%), see {} and {}",
+            name, mismatched, fraction * 100.0, actual_path.display(), diff_path.display());
+    }
+}
+
+#[test]
+fn facing_wall() {
+    check("facing_wall", &view((3.5, 3.5), 180.0, 0.0), Projection::Perspective);
+}
+
+#[test]
+fn corner() {
+    check("corner", &view((2.5, 2.5), 225.0, 0.0), Projection::Perspective);
+}
+
+#[test]
+fn through_glass_to_sprite() {
+    check("through_glass_to_sprite", &view((2.5, 2.2), 20.0, 0.0), Projection::Perspective);
+}
+
+/// The sprite seen through the glass has to be tinted by it, not drawn over it
+#[test]
+fn sprite_behind_glass_is_tinted() {
+    let player = view((2.5, 2.2), 20.0, 0.0);
+    let render = |glass: bool, sprite: bool| {
+        let mut map = level();
+        if !glass {
+            map.set(3, 2, 0);
+        }
+        if !sprite {
+            map.sprites.clear();
+        }
+
+        let mut renderer = Renderer::new(WIDTH, HEIGHT);
+        renderer.render(&map, &player);
+        renderer.framebuffer.iter().map(|&p| unpack(p)).collect::<Vec<_>>()
+    };
+
+    // The sprite covers the pixels it changes when there is no glass in the way
+    let bare = render(false, true);
+    let sprite: Vec<usize> = bare.iter().zip(render(false, false))
+        .enumerate()
+        .filter(|(_, (a, b))| **a != *b)
+        .map(|(i, _)| i)
+        .collect();
+    assert!(!sprite.is_empty(), "the sprite isn't in view");
+
+    let behind_glass = render(true, true);
+    for &i in sprite.iter() {
+        assert_ne!(behind_glass[i], bare[i], "sprite pixel ({}, {}) has no glass over it", i as u32 % WIDTH, i as u32 / WIDTH);
+    }
+
+    // The light blue glass over the dark yellow sprite brightens its middle, mostly in blue
+    let middle = sprite[sprite.len() / 2];
+    let (tinted, plain) = (behind_glass[middle], bare[middle]);
+    assert!(tinted.b > plain.b + 50 && tinted.g > plain.g, "{:?} is not tinted from {:?}", tinted, plain);
+}
+
+#[test]
+fn door_and_sprite() {
+    check("door_and_sprite", &view((4.5, 3.5), 0.0, 0.0), Projection::Perspective);
+}
+
+#[test]
+fn looking_up_and_down() {
+    check("looking_up", &view((3.5, 4.5), 300.0, 0.3), Projection::Perspective);
+    check("looking_down", &view((3.5, 4.5), 300.0, -0.3), Projection::Perspective);
+}
+
+#[test]
+fn fisheye() {
+    check("fisheye", &view((2.5, 3.5), 15.0, 0.0), Projection::Fisheye);
+}
+
+#[test]
+fn debug_overlay() {
+    let mut renderer = Renderer::new(WIDTH, HEIGHT);
+    renderer.debug_overlay = true;
+    check_with("debug_overlay", &view((2.5, 2.5), 30.0, 0.0), renderer);
+}